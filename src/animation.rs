@@ -0,0 +1,299 @@
+use crate::automaton::Automaton;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::path::PathBuf;
+use thiserror::Error as ThisError;
+
+#[derive(ThisError, Debug)]
+pub enum Error {
+    #[error("failed to write frame: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+// One generation drawn as text. Cells are `#` when alive, taken or black, `.`
+// when not, `L` for empty seats and a space where there is nothing to draw.
+// Simulations with more than two dimensions draw one labelled layer per
+// slice.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Frame {
+    layers: Vec<Layer>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Layer {
+    label: Option<String>,
+    rows: Vec<String>,
+}
+
+pub trait Render {
+    fn render(&self) -> Frame;
+}
+
+// Collects frames as a simulation runs, to write out once it is done.
+#[derive(Default)]
+pub struct Recorder {
+    frames: Vec<Frame>,
+}
+
+impl Frame {
+    pub fn new(rows: Vec<String>) -> Frame {
+        Frame {
+            layers: vec![Layer { label: None, rows }],
+        }
+    }
+
+    pub fn with_layer(mut self, label: &str, rows: Vec<String>) -> Frame {
+        self.layers.push(Layer {
+            label: Some(label.to_string()),
+            rows,
+        });
+        self
+    }
+
+    pub fn to_ascii(&self) -> String {
+        let mut out = String::new();
+        for (i, layer) in self.layers.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            if let Some(label) = &layer.label {
+                writeln!(out, "{}", label).unwrap();
+            }
+            for row in layer.rows.iter() {
+                writeln!(out, "{}", row).unwrap();
+            }
+        }
+        out
+    }
+
+    // A binary PPM with the layers side by side, one cell apart, and every
+    // cell `scale` pixels across.
+    pub fn to_ppm(&self, scale: usize) -> Vec<u8> {
+        let widths = self
+            .layers
+            .iter()
+            .map(|l| l.rows.iter().map(|r| r.chars().count()).max().unwrap_or(0))
+            .collect::<Vec<_>>();
+        let width = widths.iter().sum::<usize>() + widths.len().saturating_sub(1);
+        let height = self.layers.iter().map(|l| l.rows.len()).max().unwrap_or(0);
+        let mut cells = vec![vec![' '; width]; height];
+        let mut left = 0;
+        for (layer, layer_width) in self.layers.iter().zip(widths) {
+            for (row, line) in layer.rows.iter().enumerate() {
+                for (col, c) in line.chars().enumerate() {
+                    cells[row][left + col] = c;
+                }
+            }
+            left += layer_width + 1;
+        }
+
+        let mut out = format!("P6\n{} {}\n255\n", width * scale, height * scale).into_bytes();
+        for row in cells.iter() {
+            for _ in 0..scale {
+                for &c in row.iter() {
+                    for _ in 0..scale {
+                        out.extend_from_slice(&color(c));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    fn size(&self) -> (usize, usize) {
+        let text = self.to_ascii();
+        let width = text.lines().map(|l| l.chars().count()).max().unwrap_or(0);
+        (width, text.lines().count())
+    }
+}
+
+fn color(c: char) -> [u8; 3] {
+    match c {
+        '#' => [40, 40, 40],
+        '.' => [230, 230, 230],
+        'L' => [120, 160, 220],
+        ' ' => [255, 255, 255],
+        _ => [200, 60, 60],
+    }
+}
+
+// Quoted for JSON, which asciinema casts are written in.
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder { frames: Vec::new() }
+    }
+
+    pub fn record(&mut self, r: &impl Render) {
+        self.frames.push(r.render());
+    }
+
+    // Records `start` and the next `generations` generations after it, and
+    // returns the last.
+    pub fn run<A: Automaton + Render>(&mut self, start: &A, generations: usize) -> A {
+        let mut current = start.clone();
+        self.record(&current);
+        for _ in 0..generations {
+            current = current.step();
+            self.record(&current);
+        }
+        current
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn to_ascii(&self) -> String {
+        let mut out = String::new();
+        for (i, frame) in self.frames.iter().enumerate() {
+            write!(out, "Generation {}:\n{}\n", i, frame.to_ascii()).unwrap();
+        }
+        out
+    }
+
+    // An asciinema v2 cast that redraws the screen every `seconds` seconds.
+    pub fn to_cast(&self, seconds: f64) -> String {
+        let header = |i| format!("Generation {}", i);
+        let (width, height) = self
+            .frames
+            .iter()
+            .map(Frame::size)
+            .enumerate()
+            .fold((1, 1), |(w, h), (i, (fw, fh))| {
+                (w.max(fw).max(header(i).len()), h.max(fh + 1))
+            });
+        let mut out = format!(
+            "{{\"version\": 2, \"width\": {}, \"height\": {}}}\n",
+            width, height
+        );
+        for (i, frame) in self.frames.iter().enumerate() {
+            let screen = format!(
+                "\x1b[2J\x1b[H{}\r\n{}",
+                header(i),
+                frame.to_ascii().replace('\n', "\r\n")
+            );
+            writeln!(
+                out,
+                "[{:.6}, \"o\", {}]",
+                i as f64 * seconds,
+                json_string(&screen)
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    // Writes `frame_0000.ppm` and so on into `dir`, which must exist, and
+    // returns their paths.
+    pub fn write_ppm(&self, dir: &Path, scale: usize) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for (i, frame) in self.frames.iter().enumerate() {
+            let path = dir.join(format!("frame_{:04}.ppm", i));
+            std::fs::write(&path, frame.to_ppm(scale))?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A dot that moves one cell right every generation, wrapping at 3.
+    #[derive(Clone)]
+    struct Dot(usize);
+
+    impl Automaton for Dot {
+        type Key = usize;
+
+        fn key(&self) -> usize {
+            self.0
+        }
+
+        fn step(&self) -> Dot {
+            Dot((self.0 + 1) % 3)
+        }
+    }
+
+    impl Render for Dot {
+        fn render(&self) -> Frame {
+            let row = (0..3).map(|i| if i == self.0 { '#' } else { '.' });
+            Frame::new(vec![row.collect()])
+        }
+    }
+
+    #[test]
+    fn test_ascii_and_cast() {
+        let mut recorder = Recorder::new();
+        assert_eq!(1, recorder.run(&Dot(1), 3).0);
+        assert_eq!(4, recorder.frames().len());
+        assert!(recorder
+            .to_ascii()
+            .starts_with("Generation 0:\n.#.\n\nGeneration 1:\n..#\n"));
+
+        let cast = recorder.to_cast(0.5);
+        let lines = cast.lines().collect::<Vec<_>>();
+        assert_eq!(5, lines.len());
+        assert_eq!("{\"version\": 2, \"width\": 12, \"height\": 2}", lines[0]);
+        assert_eq!(
+            "[1.000000, \"o\", \"\\u001b[2J\\u001b[HGeneration 2\\r\\n#..\\r\\n\"]",
+            lines[3]
+        );
+
+        let frame = Frame::default()
+            .with_layer("z=0", vec!["#.".to_string()])
+            .with_layer("z=1", vec!["L".to_string()]);
+        assert_eq!("z=0\n#.\n\nz=1\nL\n", frame.to_ascii());
+    }
+
+    #[test]
+    fn test_ppm() {
+        let frame = Frame::default()
+            .with_layer("z=0", vec!["#.".to_string(), ".".to_string()])
+            .with_layer("z=1", vec!["L".to_string()]);
+        let ppm = frame.to_ppm(2);
+        let header = b"P6\n8 4\n255\n";
+        assert_eq!(&header[..], &ppm[..header.len()]);
+        assert_eq!(header.len() + 8 * 4 * 3, ppm.len());
+        let pixel = |x: usize, y: usize| {
+            let i = header.len() + (y * 8 + x) * 3;
+            [ppm[i], ppm[i + 1], ppm[i + 2]]
+        };
+        assert_eq!(color('#'), pixel(1, 1));
+        assert_eq!(color('.'), pixel(2, 0));
+        assert_eq!(color(' '), pixel(2, 2));
+        assert_eq!(color(' '), pixel(4, 0));
+        assert_eq!(color('L'), pixel(7, 1));
+
+        let dir = std::env::temp_dir().join(format!("advent_2020_ppm_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut recorder = Recorder::new();
+        recorder.run(&Dot(0), 2);
+        let paths = recorder.write_ppm(&dir, 1).unwrap();
+        assert_eq!(dir.join("frame_0002.ppm"), paths[2]);
+        assert_eq!(
+            recorder.frames()[1].to_ppm(1),
+            std::fs::read(&paths[1]).unwrap()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
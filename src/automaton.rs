@@ -0,0 +1,235 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+// A simulation that moves from one generation to the next by a fixed rule,
+// such as `cube::Space`, `ferry::WaitingArea` or `tile::Map`.
+pub trait Automaton: Clone {
+    // Equal for two generations exactly when they are the same state.
+    type Key: Hash + Eq;
+
+    fn key(&self) -> Self::Key;
+    fn step(&self) -> Self;
+}
+
+// Generation `pre_period + period` is the same as generation `pre_period`,
+// and no earlier pair of generations repeats. A fixed point has period 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cycle {
+    pub pre_period: usize,
+    pub period: usize,
+}
+
+// Steps an automaton while remembering a hash of every generation, so that
+// once one repeats any later generation can be found without simulating it.
+// Only the first and latest generations are kept, plus those on the cycle
+// once it is found; anything else is recomputed from the start.
+pub struct Driver<A: Automaton> {
+    start: A,
+    current: A,
+    generation: usize,
+    // Generations by the hash of their key; more than one on a collision.
+    seen: HashMap<u64, Vec<usize>>,
+    cycle: Option<Cycle>,
+    // Generations `pre_period..pre_period + period`, once the cycle is known.
+    period_states: Vec<A>,
+}
+
+fn hash<K: Hash>(key: &K) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl Cycle {
+    pub fn is_fixed_point(&self) -> bool {
+        self.period == 1
+    }
+
+    // Which generation before the cycle first comes round again has the same
+    // state as generation `n`.
+    pub fn equivalent(&self, n: usize) -> usize {
+        match n < self.pre_period {
+            true => n,
+            false => self.pre_period + (n - self.pre_period) % self.period,
+        }
+    }
+}
+
+impl<A: Automaton> Driver<A> {
+    pub fn new(start: A) -> Driver<A> {
+        let mut seen = HashMap::new();
+        seen.insert(hash(&start.key()), vec![0]);
+        Driver {
+            current: start.clone(),
+            start,
+            generation: 0,
+            seen,
+            cycle: None,
+            period_states: Vec::new(),
+        }
+    }
+
+    // The latest generation simulated.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    pub fn current(&self) -> &A {
+        &self.current
+    }
+
+    pub fn cycle(&self) -> Option<Cycle> {
+        self.cycle
+    }
+
+    // Generation `n`, which must not be later than the latest one, simulated
+    // again from the start.
+    fn replay(&self, n: usize) -> A {
+        if n == self.generation {
+            return self.current.clone();
+        }
+        let mut state = self.start.clone();
+        for _ in 0..n {
+            state = state.step();
+        }
+        state
+    }
+
+    // Simulates one more generation, unless a cycle has already been found.
+    // Returns the cycle if this step found one.
+    fn advance(&mut self) -> Option<Cycle> {
+        if self.cycle.is_some() {
+            return self.cycle;
+        }
+        let next = self.current.step();
+        let key = next.key();
+        let n = self.generation + 1;
+        let candidates = self.seen.get(&hash(&key)).cloned().unwrap_or_default();
+        for g in candidates {
+            let earlier = self.replay(g);
+            if earlier.key() != key {
+                continue;
+            }
+            let mut state = earlier;
+            for _ in g..n {
+                let next = state.step();
+                self.period_states.push(std::mem::replace(&mut state, next));
+            }
+            self.cycle = Some(Cycle {
+                pre_period: g,
+                period: n - g,
+            });
+            return self.cycle;
+        }
+        self.seen.entry(hash(&key)).or_default().push(n);
+        self.current = next;
+        self.generation = n;
+        None
+    }
+
+    // Simulates up to `max_steps` more generations, stopping early once a
+    // cycle turns up.
+    pub fn run(&mut self, max_steps: usize) -> Option<Cycle> {
+        for _ in 0..max_steps {
+            if self.advance().is_some() {
+                break;
+            }
+        }
+        self.cycle
+    }
+
+    // Keeps going until a generation repeats, which never happens for an
+    // automaton that grows forever.
+    pub fn run_until_cycle(&mut self) -> Cycle {
+        loop {
+            if let Some(cycle) = self.advance() {
+                return cycle;
+            }
+        }
+    }
+
+    // Generation `n`, skipping ahead through the cycle once it is known.
+    pub fn nth(&mut self, n: usize) -> A {
+        while n > self.generation() && self.advance().is_none() {}
+        match self.cycle {
+            Some(cycle) if n >= cycle.pre_period => {
+                self.period_states[cycle.equivalent(n) - cycle.pre_period].clone()
+            }
+            _ => self.replay(n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counts up to `top`, then goes back to `back`.
+    #[derive(Clone)]
+    struct Counter {
+        value: usize,
+        top: usize,
+        back: usize,
+    }
+
+    impl Automaton for Counter {
+        type Key = usize;
+
+        fn key(&self) -> usize {
+            self.value
+        }
+
+        fn step(&self) -> Counter {
+            Counter {
+                value: if self.value == self.top {
+                    self.back
+                } else {
+                    self.value + 1
+                },
+                ..*self
+            }
+        }
+    }
+
+    #[test]
+    fn test_cycle() {
+        let mut driver = Driver::new(Counter {
+            value: 0,
+            top: 10,
+            back: 5,
+        });
+        assert_eq!(None, driver.run(5));
+        assert_eq!(5, driver.current().value);
+        let cycle = driver.run_until_cycle();
+        assert_eq!(
+            Cycle {
+                pre_period: 5,
+                period: 6
+            },
+            cycle
+        );
+        assert!(!cycle.is_fixed_point());
+        assert_eq!(10, driver.generation());
+        assert_eq!(10, driver.nth(1_000_000_000_000).value);
+        assert_eq!(3, driver.nth(3).value);
+        assert_eq!(10, driver.generation());
+    }
+
+    #[test]
+    fn test_fixed_point() {
+        let mut driver = Driver::new(Counter {
+            value: 0,
+            top: 3,
+            back: 3,
+        });
+        assert_eq!(2, driver.nth(2).value);
+        assert_eq!(None, driver.cycle());
+        assert_eq!(3, driver.nth(100).value);
+        let cycle = driver.cycle().unwrap();
+        assert!(cycle.is_fixed_point());
+        assert_eq!(3, cycle.pre_period);
+        assert_eq!(3, driver.generation());
+    }
+}
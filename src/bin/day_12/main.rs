@@ -1,4 +1,5 @@
 use advent_2020::ferry;
+use advent_2020::ferry::Navigator;
 
 fn main() {
     let input = std::fs::read_to_string("src/bin/day_12/input.txt").unwrap();
@@ -7,11 +7,13 @@ fn main() {
     let mut rule_map = rules_section.parse::<sea_monster::RuleMap>().unwrap();
 
     let input_strs = input_section.trim().lines().collect::<Vec<_>>();
-    let valid = input_strs.iter().filter(|&&s| rule_map.matches(s));
+    let parser = rule_map.compile().unwrap();
+    let valid = input_strs.iter().filter(|&&s| parser.matches(s));
     println!("Part 1: {}", valid.count());
 
     rule_map.rewrite(8, "42 | 42 8").unwrap();
     rule_map.rewrite(11, "42 31 | 42 11 31").unwrap();
-    let valid = input_strs.iter().filter(|&&s| rule_map.matches(s));
+    let parser = rule_map.compile().unwrap();
+    let valid = input_strs.iter().filter(|&&s| parser.matches(s));
     println!("Part 2: {}", valid.count());
 }
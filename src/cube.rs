@@ -1,5 +1,10 @@
+use crate::animation::Frame;
+use crate::animation::Render;
+use crate::automaton::Automaton;
 use itertools::Itertools;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use thiserror::Error as ThisError;
 
 #[derive(ThisError, Debug)]
@@ -9,6 +14,12 @@ pub enum Error {
 
     #[error("failed to parse state: {0}")]
     UnknownStateError(char),
+
+    #[error("failed to parse rules: {0}")]
+    RulesParseError(String),
+
+    #[error("a starting slice needs at least 2 dimensions, not {0}")]
+    DimensionError(usize),
 }
 
 type Result<T> = std::result::Result<T, Error>;
@@ -19,269 +30,382 @@ enum State {
     Inactive,
 }
 
-#[derive(Hash, PartialEq, Eq, Clone, Debug)]
-struct Coord3 {
-    x: i32,
-    y: i32,
-    z: i32,
-}
+type Coord<const D: usize> = [i32; D];
 
-#[derive(Hash, PartialEq, Eq, Clone, Debug)]
-struct Coord4 {
-    x: i32,
-    y: i32,
-    z: i32,
-    w: i32,
+// Life-like rules in B/S notation, e.g. `B3/S23` for Conway's rules. Counts
+// above 9 are written with commas between them, as in `B3,10/S2,3`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rules {
+    birth: BTreeSet<usize>,
+    survival: BTreeSet<usize>,
 }
 
-#[derive(Clone, Debug)]
-pub struct Space3 {
-    states: HashMap<Coord3, State>,
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Neighborhood {
+    // Every cell within `radius` along all axes at once.
+    Moore(u32),
+    // Every cell within a Manhattan distance of `radius`.
+    VonNeumann(u32),
 }
 
+// Only active cells are stored. A slice started in the plane stays the same
+// under mirroring or swapping any of the other dimensions, so while `folded`
+// only cells with those coordinates non-negative and in ascending order are
+// kept.
 #[derive(Clone, Debug)]
-pub struct Space4 {
-    states: HashMap<Coord4, State>,
+pub struct Space<const D: usize> {
+    active: HashSet<Coord<D>>,
+    folded: bool,
+    rules: Rules,
+    offsets: Vec<Coord<D>>,
 }
 
-impl Space3 {
-    pub fn from_initial_slice(slice_str: &str) -> Result<Space3> {
-        let x = slice_str
-            .trim()
-            .lines()
-            .map(|line| {
-                line.chars()
-                    .map(|c| match c {
-                        '.' => Ok(State::Inactive),
-                        '#' => Ok(State::Active),
-                        c => Err(Error::UnknownStateError(c)),
-                    })
-                    .collect::<Result<Vec<_>>>()
-            })
-            .collect::<Result<Vec<_>>>()?;
-        let mut active = HashMap::new();
-        let _ = x
-            .into_iter()
-            .enumerate()
-            .map(|(i, v)| {
-                v.into_iter()
-                    .enumerate()
-                    .map(|(j, state)| {
-                        match state {
-                            State::Active => {
-                                let c = Coord3 {
-                                    x: i as i32,
-                                    y: j as i32,
-                                    z: 0,
-                                };
-                                active.insert(c, state);
-                            }
-                            State::Inactive => (),
-                        };
-                    })
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
-        let mut s = Space3 { states: active };
-        s.pad();
-        Ok(s)
+impl Rules {
+    pub fn conway() -> Rules {
+        "B3/S23".parse().unwrap()
     }
 
-    pub fn active_count(&self) -> usize {
-        self.states
-            .iter()
-            .map(|(_, v)| match *v {
-                State::Active => 1,
-                State::Inactive => 0,
-            })
-            .sum()
+    fn next(&self, state: &State, active_neighbors: usize) -> State {
+        let counts = match state {
+            State::Active => &self.survival,
+            State::Inactive => &self.birth,
+        };
+        match counts.contains(&active_neighbors) {
+            true => State::Active,
+            false => State::Inactive,
+        }
     }
+}
 
-    fn neighbors_active_count(&self, coord: &Coord3) -> usize {
-        let neighbors = coord.neighbors();
-        neighbors
-            .into_iter()
-            .map(|n| match self.states.get(&n) {
-                Some(&State::Active) => 1,
-                _ => 0,
-            })
-            .sum()
+impl std::str::FromStr for Rules {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let err = || Error::RulesParseError(s.to_string());
+        let counts = |part: &str, prefix: char| {
+            let part = part
+                .trim()
+                .strip_prefix(prefix)
+                .or_else(|| part.trim().strip_prefix(prefix.to_ascii_lowercase()))
+                .ok_or_else(err)?;
+            match part.contains(',') {
+                true => part
+                    .split(',')
+                    .map(|n| n.trim().parse::<usize>().map_err(|_| err()))
+                    .collect::<Result<BTreeSet<_>>>(),
+                false => part
+                    .chars()
+                    .map(|c| c.to_digit(10).map(|d| d as usize).ok_or_else(err))
+                    .collect::<Result<BTreeSet<_>>>(),
+            }
+        };
+        match s.split('/').collect::<Vec<_>>()[..] {
+            [birth, survival] => Ok(Rules {
+                birth: counts(birth, 'B')?,
+                survival: counts(survival, 'S')?,
+            }),
+            _ => Err(err()),
+        }
     }
+}
 
-    pub fn step(&self) -> Space3 {
-        let mut new = self.clone();
-        let _ = self
-            .states
+impl std::fmt::Display for Rules {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let commas = self
+            .birth
             .iter()
-            .map(|(coord, state)| {
-                let new_state = match self.neighbors_active_count(coord) {
-                    2..=3 if state == &State::Active => State::Active,
-                    3 if state == &State::Inactive => State::Active,
-                    _ => State::Inactive,
-                };
-                new.states.insert(coord.clone(), new_state);
-            })
-            .collect::<Vec<_>>();
-        new.pad();
-        new
+            .chain(self.survival.iter())
+            .any(|&n| n > 9);
+        let counts = |counts: &BTreeSet<usize>| {
+            let counts = counts.iter().map(usize::to_string).collect::<Vec<_>>();
+            counts.join(if commas { "," } else { "" })
+        };
+        write!(f, "B{}/S{}", counts(&self.birth), counts(&self.survival))
     }
+}
 
-    fn pad(&mut self) {
-        let mut new_states = self.states.clone();
-        for (coord, _val) in self.states.iter() {
-            let neighbors = coord.neighbors();
-            for n in neighbors.into_iter() {
-                new_states.entry(n).or_insert(State::Inactive);
+impl Neighborhood {
+    // Offsets from a cell to each of its neighbors.
+    fn offsets<const D: usize>(&self) -> Vec<Coord<D>> {
+        let r = match *self {
+            Neighborhood::Moore(r) | Neighborhood::VonNeumann(r) => r as i32,
+        };
+        let mut offsets = Vec::new();
+        let mut offset = [-r; D];
+        loop {
+            let distance = offset.iter().map(|c| c.abs()).sum::<i32>();
+            let included = match self {
+                Neighborhood::Moore(_) => true,
+                Neighborhood::VonNeumann(_) => distance <= r,
+            };
+            if included && distance != 0 {
+                offsets.push(offset);
+            }
+            // Count through every offset in the cube like an odometer.
+            match offset.iter().position(|&c| c < r) {
+                Some(i) => {
+                    offset[i] += 1;
+                    for c in offset[..i].iter_mut() {
+                        *c = -r;
+                    }
+                }
+                None => return offsets,
             }
         }
-        self.states = new_states;
     }
 }
 
-impl Space4 {
-    pub fn from_initial_slice(slice_str: &str) -> Result<Space4> {
-        let x = slice_str
-            .trim()
-            .lines()
-            .map(|line| {
-                line.chars()
-                    .map(|c| match c {
-                        '.' => Ok(State::Inactive),
-                        '#' => Ok(State::Active),
-                        c => Err(Error::UnknownStateError(c)),
-                    })
-                    .collect::<Result<Vec<_>>>()
-            })
-            .collect::<Result<Vec<_>>>()?;
-        let mut active = HashMap::new();
-        let _ = x
-            .into_iter()
-            .enumerate()
-            .map(|(i, v)| {
-                v.into_iter()
-                    .enumerate()
-                    .map(|(j, state)| {
-                        match state {
-                            State::Active => {
-                                let c = Coord4 {
-                                    x: i as i32,
-                                    y: j as i32,
-                                    z: 0,
-                                    w: 0,
-                                };
-                                active.insert(c, state);
-                            }
-                            State::Inactive => (),
-                        };
-                    })
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
-        let mut s = Space4 { states: active };
-        s.pad();
-        Ok(s)
+fn add<const D: usize>(a: &Coord<D>, b: &Coord<D>) -> Coord<D> {
+    let mut sum = *a;
+    for (s, b) in sum.iter_mut().zip(b.iter()) {
+        *s += b;
     }
+    sum
+}
 
-    pub fn active_count(&self) -> usize {
-        self.states
-            .iter()
-            .map(|(_, v)| match *v {
-                State::Active => 1,
-                State::Inactive => 0,
-            })
-            .sum()
+impl<const D: usize> Space<D> {
+    // The slice lies in the first two dimensions, with every other coordinate
+    // 0. Uses Conway's rules and the 3^D - 1 surrounding cells as neighbors
+    // until told otherwise.
+    pub fn from_initial_slice(slice_str: &str) -> Result<Space<D>> {
+        if D < 2 {
+            return Err(Error::DimensionError(D));
+        }
+        let mut active = HashSet::new();
+        for (i, line) in slice_str.trim().lines().enumerate() {
+            for (j, c) in line.trim().chars().enumerate() {
+                match c {
+                    '.' => (),
+                    '#' => {
+                        let mut coord = [0; D];
+                        coord[0] = i as i32;
+                        coord[1] = j as i32;
+                        active.insert(coord);
+                    }
+                    c => return Err(Error::UnknownStateError(c)),
+                }
+            }
+        }
+        Ok(Space {
+            active,
+            folded: true,
+            rules: Rules::conway(),
+            offsets: Neighborhood::Moore(1).offsets(),
+        })
     }
 
-    fn neighbors_active_count(&self, coord: &Coord4) -> usize {
-        let neighbors = coord.neighbors();
-        neighbors
-            .into_iter()
-            .map(|n| match self.states.get(&n) {
-                Some(&State::Active) => 1,
-                _ => 0,
-            })
-            .sum()
+    pub fn rules(mut self, rules: Rules) -> Space<D> {
+        self.rules = rules;
+        self
     }
 
-    pub fn step(&self) -> Space4 {
-        let mut new = self.clone();
-        let _ = self
-            .states
-            .iter()
-            .map(|(coord, state)| {
-                let new_state = match self.neighbors_active_count(coord) {
-                    2..=3 if state == &State::Active => State::Active,
-                    3 if state == &State::Inactive => State::Active,
-                    _ => State::Inactive,
-                };
-                new.states.insert(coord.clone(), new_state);
-            })
-            .collect::<Vec<_>>();
-        new.pad();
-        new
+    pub fn neighborhood(mut self, neighborhood: Neighborhood) -> Space<D> {
+        self.offsets = neighborhood.offsets();
+        self
+    }
+
+    // Stores every active cell rather than one per mirror image, mostly for
+    // checking the folded simulation against.
+    pub fn unfolded(self) -> Space<D> {
+        let active = self.active.iter().flat_map(images).collect();
+        Space {
+            active,
+            folded: false,
+            ..self
+        }
+    }
+
+    pub fn active_count(&self) -> usize {
+        self.active.iter().map(|c| self.multiplicity(c)).sum()
     }
 
-    fn pad(&mut self) {
-        let mut new_states = self.states.clone();
-        for (coord, _val) in self.states.iter() {
-            let neighbors = coord.neighbors();
-            for n in neighbors.into_iter() {
-                new_states.entry(n).or_insert(State::Inactive);
+    fn canonical(&self, mut coord: Coord<D>) -> Coord<D> {
+        if self.folded {
+            for c in coord[2..].iter_mut() {
+                *c = c.abs();
             }
+            coord[2..].sort_unstable();
         }
-        self.states = new_states;
+        coord
     }
-}
-impl Coord3 {
-    fn neighbors(&self) -> Vec<Coord3> {
-        (-1..=1)
-            .cartesian_product(-1..=1)
-            .cartesian_product(-1..=1)
-            .map(|c| (c.0 .0, c.0 .1, c.1))
-            .filter(|c| c != &(0, 0, 0))
-            .map(|(x, y, z)| Coord3 { x, y, z })
-            .map(|c| self.add(c))
-            .collect::<Vec<_>>()
+
+    // How many cells a stored cell stands for: every way of permuting and
+    // negating its coordinates past the first two.
+    fn multiplicity(&self, coord: &Coord<D>) -> usize {
+        if !self.folded {
+            return 1;
+        }
+        let folded = &coord[2..];
+        let mut permutations = factorial(folded.len());
+        for (_, run) in &folded.iter().group_by(|&&c| c) {
+            permutations /= factorial(run.count());
+        }
+        permutations << folded.iter().filter(|&&c| c != 0).count()
     }
 
-    fn add(&self, other: Coord3) -> Coord3 {
-        Coord3 {
-            x: self.x + other.x,
-            y: self.y + other.y,
-            z: self.z + other.z,
+    // Each active cell adds itself to the counts of its neighbors, so only
+    // cells next to an active one are ever looked at.
+    pub fn step(&self) -> Space<D> {
+        let mut counts = HashMap::new();
+        for coord in self.active.iter() {
+            let weight = self.multiplicity(coord);
+            for o in self.offsets.iter() {
+                *counts.entry(self.canonical(add(coord, o))).or_insert(0) += weight;
+            }
+        }
+        // Scattering from one stored cell reaches each stored neighbor once
+        // per offset, but a neighbor with n mirror images hears from every
+        // image of the cell, so each count is n times too high. Active cells
+        // with no active neighbors never show up in the counts.
+        let lonely = match self.rules.next(&State::Active, 0) {
+            State::Active => self
+                .active
+                .iter()
+                .filter(|c| !counts.contains_key(*c))
+                .copied()
+                .collect(),
+            State::Inactive => Vec::new(),
+        };
+        let mut active = counts
+            .into_iter()
+            .filter(|(coord, weight)| {
+                let state = match self.active.contains(coord) {
+                    true => State::Active,
+                    false => State::Inactive,
+                };
+                self.rules.next(&state, weight / self.multiplicity(coord)) == State::Active
+            })
+            .map(|(coord, _)| coord)
+            .collect::<HashSet<_>>();
+        active.extend(lonely);
+        Space {
+            active,
+            ..self.clone()
         }
     }
 }
 
-impl Coord4 {
-    fn neighbors(&self) -> Vec<Coord4> {
-        (-1..=1)
-            .cartesian_product(-1..=1)
-            .cartesian_product(-1..=1)
-            .cartesian_product(-1..=1)
-            .map(|c| (c.0 .0 .0, c.0 .0 .1, c.0 .1, c.1))
-            .filter(|c| c != &(0, 0, 0, 0))
-            .map(|(x, y, z, w)| Coord4 { x, y, z, w })
-            .map(|c| self.add(c))
-            .collect::<Vec<_>>()
+impl<const D: usize> Automaton for Space<D> {
+    type Key = Vec<Coord<D>>;
+
+    fn key(&self) -> Self::Key {
+        self.active.iter().copied().sorted().collect()
     }
 
-    fn add(&self, other: Coord4) -> Coord4 {
-        Coord4 {
-            x: self.x + other.x,
-            y: self.y + other.y,
-            z: self.z + other.z,
-            w: self.w + other.w,
+    fn step(&self) -> Self {
+        Space::step(self)
+    }
+}
+
+// One layer per slice through the first two dimensions, labelled like
+// `z=-1, w=0` and all cut to the same bounds.
+impl<const D: usize> Render for Space<D> {
+    fn render(&self) -> Frame {
+        let active = match self.folded {
+            true => self.active.iter().flat_map(images).collect(),
+            false => self.active.clone(),
+        };
+        let mut min = [0; D];
+        let mut max = [0; D];
+        for (i, coord) in active.iter().enumerate() {
+            for d in 0..D {
+                min[d] = if i == 0 {
+                    coord[d]
+                } else {
+                    min[d].min(coord[d])
+                };
+                max[d] = if i == 0 {
+                    coord[d]
+                } else {
+                    max[d].max(coord[d])
+                };
+            }
+        }
+        let names = ["z", "w"];
+        let mut frame = Frame::default();
+        let mut coord = min;
+        loop {
+            let rows = (min[0]..=max[0])
+                .map(|i| {
+                    coord[0] = i;
+                    (min[1]..=max[1])
+                        .map(|j| {
+                            coord[1] = j;
+                            if active.contains(&coord) {
+                                '#'
+                            } else {
+                                '.'
+                            }
+                        })
+                        .collect()
+                })
+                .collect();
+            if D == 2 {
+                return Frame::new(rows);
+            }
+            let label = (2..D)
+                .map(|d| match names.get(d - 2) {
+                    Some(name) => format!("{}={}", name, coord[d]),
+                    None => format!("x{}={}", d, coord[d]),
+                })
+                .join(", ");
+            frame = frame.with_layer(&label, rows);
+            // Odometer over the slices.
+            match (2..D).find(|&d| coord[d] < max[d]) {
+                Some(d) => {
+                    coord[d] += 1;
+                    coord[2..d].copy_from_slice(&min[2..d]);
+                }
+                None => return frame,
+            }
         }
     }
 }
+
+fn factorial(n: usize) -> usize {
+    (1..=n).product()
+}
+
+// Every cell that the folded `coord` stands for.
+fn images<const D: usize>(coord: &Coord<D>) -> Vec<Coord<D>> {
+    let mut images = vec![*coord];
+    for i in 2..D {
+        images = images
+            .into_iter()
+            .flat_map(|c| {
+                let mut flipped = c;
+                flipped[i] = -c[i];
+                Some(c)
+                    .into_iter()
+                    .chain(Some(flipped).filter(|_| c[i] != 0))
+            })
+            .collect();
+    }
+    images
+        .into_iter()
+        .flat_map(|c| {
+            let folded = c[2..].to_vec();
+            folded.into_iter().permutations(D - 2).map(move |folded| {
+                let mut image = c;
+                image[2..].copy_from_slice(&folded);
+                image
+            })
+        })
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::automaton::Driver;
+
+    static TEST_SLICE: &str = ".#.\n..#\n###";
 
     #[test]
     fn test_space3_step() {
-        let space = Space3::from_initial_slice(".#.\n..#\n###").unwrap();
+        let space = Space::<3>::from_initial_slice(TEST_SLICE).unwrap();
         assert_eq!(5, space.active_count());
         let space = space.step();
         assert_eq!(11, space.active_count());
@@ -296,9 +420,28 @@ mod tests {
     }
 
     #[test]
-    #[cfg_attr(not(feature = "expensive_tests"), ignore)]
+    fn test_render() {
+        let space = Space::<3>::from_initial_slice(TEST_SLICE).unwrap().step();
+        assert_eq!(
+            "z=-1\n#..\n..#\n.#.\n\nz=0\n#.#\n.##\n.#.\n\nz=1\n#..\n..#\n.#.\n",
+            space.render().to_ascii()
+        );
+        let space = Space::<4>::from_initial_slice(TEST_SLICE).unwrap().step();
+        let ascii = space.render().to_ascii();
+        assert_eq!(9, ascii.matches("z=").count());
+        assert!(ascii.contains("z=0, w=0\n#.#\n.##\n.#.\n"));
+        assert_eq!(
+            "#.\n.#\n",
+            Space::<2>::from_initial_slice("#.\n.#")
+                .unwrap()
+                .render()
+                .to_ascii()
+        );
+    }
+
+    #[test]
     fn test_space4_step() {
-        let space = Space4::from_initial_slice(".#.\n..#\n###").unwrap();
+        let space = Space::<4>::from_initial_slice(TEST_SLICE).unwrap();
         assert_eq!(5, space.active_count());
         let space = space.step();
         assert_eq!(29, space.active_count());
@@ -310,9 +453,103 @@ mod tests {
         assert_eq!(848, space.active_count());
     }
 
+    fn cycles<const D: usize>(mut space: Space<D>, n: usize) -> Space<D> {
+        for _ in 0..n {
+            space = space.step();
+        }
+        space
+    }
+
+    #[test]
+    fn test_folding() {
+        let space = Space::<5>::from_initial_slice(TEST_SLICE).unwrap();
+        let folded = cycles(space.clone(), 3);
+        let unfolded = cycles(space.unfolded(), 3);
+        assert_eq!(unfolded.active_count(), folded.active_count());
+        assert_eq!(unfolded.active.len(), folded.unfolded().active.len());
+
+        let space = Space::<4>::from_initial_slice(TEST_SLICE)
+            .unwrap()
+            .rules("B2/S1".parse().unwrap())
+            .neighborhood(Neighborhood::VonNeumann(2));
+        assert_eq!(
+            cycles(space.clone().unfolded(), 4).active_count(),
+            cycles(space, 4).active_count()
+        );
+    }
+
+    fn bench<const D: usize>() -> usize {
+        let space = Space::<D>::from_initial_slice(TEST_SLICE).unwrap();
+        let start = std::time::Instant::now();
+        let count = cycles(space.clone(), 6).active_count();
+        println!("{}D, folded: {:?}", D, start.elapsed());
+        if D <= 5 {
+            let start = std::time::Instant::now();
+            assert_eq!(count, cycles(space.unfolded(), 6).active_count());
+            println!("{}D, unfolded: {:?}", D, start.elapsed());
+        }
+        count
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "expensive_tests"), ignore)]
+    fn bench_dimensions() {
+        assert_eq!(112, bench::<3>());
+        assert_eq!(848, bench::<4>());
+        assert_eq!(5760, bench::<5>());
+        assert_eq!(35936, bench::<6>());
+        bench::<7>();
+        bench::<8>();
+    }
+
+    #[test]
+    fn test_blinker_cycle() {
+        let space = Space::<2>::from_initial_slice(".#.\n.#.\n.#.").unwrap();
+        let mut driver = Driver::new(space);
+        let cycle = driver.run_until_cycle();
+        assert_eq!((0, 2), (cycle.pre_period, cycle.period));
+        assert_eq!(3, driver.nth(1001).active_count());
+        assert!(driver.nth(1001).active.contains(&[1, 0]));
+    }
+
     #[test]
     fn test_neighbors() {
-        let n = Coord3 { x: 0, y: 0, z: 0 }.neighbors();
-        assert_eq!(26, n.len());
+        assert_eq!(26, Neighborhood::Moore(1).offsets::<3>().len());
+        assert_eq!(80, Neighborhood::Moore(1).offsets::<4>().len());
+        assert_eq!(24, Neighborhood::Moore(2).offsets::<2>().len());
+        assert_eq!(6, Neighborhood::VonNeumann(1).offsets::<3>().len());
+        assert_eq!(12, Neighborhood::VonNeumann(2).offsets::<2>().len());
+    }
+
+    #[test]
+    fn test_rules() {
+        let rules = "B36/S23".parse::<Rules>().unwrap();
+        assert_eq!("B36/S23", rules.to_string());
+        let rules = "b3,12/s2,3".parse::<Rules>().unwrap();
+        assert_eq!("B3,12/S2,3", rules.to_string());
+        assert!("B3S23".parse::<Rules>().is_err());
+        assert!("B3/Sx".parse::<Rules>().is_err());
+        assert!(matches!(
+            Space::<1>::from_initial_slice("#"),
+            Err(Error::DimensionError(1))
+        ));
+
+        // A blinker flips between a row and a column.
+        let space = Space::<2>::from_initial_slice(".#.\n.#.\n.#.").unwrap();
+        let space = space.step();
+        assert_eq!(3, space.active_count());
+        assert!(space.active.contains(&[1, 0]));
+        // Under von Neumann B1/S, a single cell grows into a diamond.
+        let space = Space::<2>::from_initial_slice("#")
+            .unwrap()
+            .rules("B1/S".parse().unwrap())
+            .neighborhood(Neighborhood::VonNeumann(1));
+        assert_eq!(4, space.step().active_count());
+        // Under B/S0 only cells without neighbors survive.
+        let rules = "B/S0".parse::<Rules>().unwrap();
+        let domino = Space::<2>::from_initial_slice("##").unwrap();
+        assert_eq!(0, domino.rules(rules.clone()).step().active_count());
+        let scattered = Space::<3>::from_initial_slice("#.#\n...\n##.").unwrap();
+        assert_eq!(2, scattered.rules(rules).step().active_count());
     }
 }
@@ -8,21 +8,64 @@ pub enum Error {
 
     #[error("failed to parse write: {0}")]
     WriteParseError(String),
+
+    #[error("failed to parse address pattern: {0}")]
+    PatternParseError(String),
 }
 
-pub struct Memory {
-    words: HashMap<u64, u64>,
-    mask: Mask,
+// A decoder chip revision, which decides how a write is applied to memory
+// under the current mask.
+pub trait Decoder {
+    fn write(&self, mask: &Mask, write: &Write, words: &mut Words);
 }
 
-pub enum Input {
-    WriteVal(Write),
-    SetMask(Mask),
+// Version 1 chips apply the mask to the value being written.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DecoderV1;
+
+// Version 2 chips apply the mask to the address, with each floating bit
+// taking both values.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DecoderV2;
+
+// A set of addresses as a ternary pattern: bits set in `floating` match
+// either value, all other bits must equal the corresponding bit of `value`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Pattern {
+    value: u64,
+    floating: u64,
+}
+
+pub struct Addresses {
+    pattern: Pattern,
+    next: Option<u64>,
+}
+
+// Memory contents. Single-address writes are kept in a map, while writes to
+// a floating pattern are kept symbolically as disjoint regions, so memory
+// stays small no matter how many floating bits a mask has.
+#[derive(Clone, Debug, Default)]
+pub struct Words {
+    concrete: HashMap<u64, u64>,
+    regions: Vec<(Pattern, u64)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference {
+    pub addresses: Pattern,
+    pub left: Option<u64>,
+    pub right: Option<u64>,
 }
 
-pub enum InputV2 {
-    WriteValV2(Write),
+pub struct Memory<D: Decoder = DecoderV1> {
+    words: Words,
+    mask: Mask,
+    decoder: D,
+}
+
+pub enum Instruction {
     SetMask(Mask),
+    Write(Write),
 }
 
 pub struct Write {
@@ -92,68 +135,449 @@ impl std::str::FromStr for Write {
     }
 }
 
-impl std::str::FromStr for Input {
+impl std::str::FromStr for Instruction {
     type Err = Error;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         if s.starts_with("mask") {
-            Ok(Input::SetMask(s.parse::<Mask>()?))
+            Ok(Instruction::SetMask(s.parse::<Mask>()?))
+        } else {
+            Ok(Instruction::Write(s.parse::<Write>()?))
+        }
+    }
+}
+
+impl Decoder for DecoderV1 {
+    fn write(&self, mask: &Mask, write: &Write, words: &mut Words) {
+        let val = write.val | mask.bits_set;
+        let val = val & mask.bits_cleared;
+        words.insert(write.addr, val);
+    }
+}
+
+impl Decoder for DecoderV2 {
+    fn write(&self, mask: &Mask, write: &Write, words: &mut Words) {
+        words.insert_pattern(mask.address_pattern(write.addr), write.val);
+    }
+}
+
+impl Pattern {
+    pub fn new(value: u64, floating: u64) -> Pattern {
+        Pattern {
+            value: value & !floating,
+            floating,
+        }
+    }
+
+    pub fn single(addr: u64) -> Pattern {
+        Pattern::new(addr, 0)
+    }
+
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+
+    pub fn floating(&self) -> u64 {
+        self.floating
+    }
+
+    pub fn contains(&self, addr: u64) -> bool {
+        addr & !self.floating == self.value
+    }
+
+    pub fn intersects(&self, other: &Pattern) -> bool {
+        let fixed = !self.floating & !other.floating;
+        (self.value ^ other.value) & fixed == 0
+    }
+
+    pub fn intersection(&self, other: &Pattern) -> Option<Pattern> {
+        if self.intersects(other) {
+            Some(Pattern::new(
+                self.value | other.value,
+                self.floating & other.floating,
+            ))
         } else {
-            Ok(Input::WriteVal(s.parse::<Write>()?))
+            None
+        }
+    }
+
+    // Number of addresses matched; a u128 since all 64 bits may float.
+    pub fn address_count(&self) -> u128 {
+        1u128 << self.floating.count_ones()
+    }
+
+    pub fn addresses(&self) -> Addresses {
+        Addresses {
+            pattern: *self,
+            next: Some(0),
+        }
+    }
+
+    // Splits the addresses matched by `self` but not by `other` into disjoint
+    // patterns. Each bit that floats in `self` but is fixed in `other` peels
+    // off one piece with that bit set opposite to `other`, so the result has
+    // at most 64 patterns.
+    pub fn subtract(&self, other: &Pattern) -> Vec<Pattern> {
+        if !self.intersects(other) {
+            return vec![*self];
+        }
+        let mut pieces = Vec::new();
+        let mut rest = *self;
+        let mut split = self.floating & !other.floating;
+        while split != 0 {
+            let bit = split & split.wrapping_neg();
+            split &= !bit;
+            rest.floating &= !bit;
+            pieces.push(Pattern {
+                value: rest.value | (!other.value & bit),
+                floating: rest.floating,
+            });
+            rest.value |= other.value & bit;
         }
+        pieces
+    }
+}
+
+// Patterns print as 36 ternary digits, like the masks that produce them,
+// widening to 64 if any higher bit is in use.
+fn width(bits: u64) -> usize {
+    if bits >> 36 == 0 {
+        36
+    } else {
+        64
     }
 }
 
-impl std::str::FromStr for InputV2 {
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for bit in (0..width(self.value | self.floating)).rev() {
+            let c = match (self.floating >> bit & 1, self.value >> bit & 1) {
+                (1, _) => 'X',
+                (_, 1) => '1',
+                _ => '0',
+            };
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Pattern {
     type Err = Error;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        if s.starts_with("mask") {
-            Ok(InputV2::SetMask(s.parse::<Mask>()?))
+        if s.is_empty() || s.len() > 64 {
+            return Err(Error::PatternParseError(s.to_string()));
+        }
+        s.chars().try_fold(Pattern::single(0), |p, c| {
+            let (value, floating) = (p.value << 1, p.floating << 1);
+            match c {
+                '0' => Ok(Pattern { value, floating }),
+                '1' => Ok(Pattern {
+                    value: value | 1,
+                    floating,
+                }),
+                'X' => Ok(Pattern {
+                    value,
+                    floating: floating | 1,
+                }),
+                _ => Err(Error::PatternParseError(s.to_string())),
+            }
+        })
+    }
+}
+
+impl Iterator for Addresses {
+    type Item = u64;
+
+    // Walks every subset of the floating bits in increasing order.
+    fn next(&mut self) -> Option<u64> {
+        let subset = self.next?;
+        let floating = self.pattern.floating;
+        let following = subset.wrapping_sub(floating) & floating;
+        self.next = if following == 0 {
+            None
         } else {
-            Ok(InputV2::WriteValV2(s.parse::<Write>()?))
+            Some(following)
+        };
+        Some(self.pattern.value | subset)
+    }
+}
+
+impl Words {
+    pub fn insert(&mut self, addr: u64, val: u64) {
+        let single = Pattern::single(addr);
+        if self.regions.iter().any(|(p, _)| p.contains(addr)) {
+            self.subtract(&single);
         }
+        self.concrete.insert(addr, val);
+    }
+
+    pub fn insert_pattern(&mut self, pattern: Pattern, val: u64) {
+        if pattern.floating == 0 {
+            return self.insert(pattern.value, val);
+        }
+        self.concrete.retain(|addr, _| !pattern.contains(*addr));
+        self.subtract(&pattern);
+        self.regions.push((pattern, val));
+    }
+
+    fn subtract(&mut self, pattern: &Pattern) {
+        self.regions = self
+            .regions
+            .iter()
+            .flat_map(|(p, val)| p.subtract(pattern).into_iter().map(move |p| (p, *val)))
+            .collect();
+    }
+
+    pub fn get(&self, addr: u64) -> Option<u64> {
+        self.concrete.get(&addr).copied().or_else(|| {
+            self.regions
+                .iter()
+                .find(|(p, _)| p.contains(addr))
+                .map(|(_, val)| *val)
+        })
+    }
+
+    // Every written address and its value: single addresses in increasing
+    // order, followed by the addresses of each floating region.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        let mut concrete = self
+            .concrete
+            .iter()
+            .map(|(a, v)| (*a, *v))
+            .collect::<Vec<_>>();
+        concrete.sort_unstable();
+        concrete.into_iter().chain(
+            self.regions
+                .iter()
+                .flat_map(|(p, val)| p.addresses().map(move |addr| (addr, *val))),
+        )
+    }
+
+    // Disjoint patterns covering all written memory, with their values.
+    pub fn entries(&self) -> Vec<(Pattern, u64)> {
+        let mut entries = self
+            .concrete
+            .iter()
+            .map(|(addr, val)| (Pattern::single(*addr), *val))
+            .chain(self.regions.iter().copied())
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|(p, _)| (p.floating, p.value));
+        entries
+    }
+
+    // Compares memories region by region rather than address by address, so
+    // it stays cheap for patterns with many floating bits.
+    pub fn diff(&self, other: &Words) -> Vec<Difference> {
+        let mut diffs = Vec::new();
+        for (addr, val) in self.concrete.iter() {
+            let right = other.get(*addr);
+            if right != Some(*val) {
+                diffs.push(Difference {
+                    addresses: Pattern::single(*addr),
+                    left: Some(*val),
+                    right,
+                });
+            }
+        }
+        for (addr, val) in other.concrete.iter() {
+            let left = self.get(*addr);
+            if !self.concrete.contains_key(addr) && left != Some(*val) {
+                diffs.push(Difference {
+                    addresses: Pattern::single(*addr),
+                    left,
+                    right: Some(*val),
+                });
+            }
+        }
+        for (pa, va) in self.regions.iter() {
+            for (pb, vb) in other.regions.iter() {
+                match pa.intersection(pb) {
+                    Some(addresses) if va != vb => diffs.push(Difference {
+                        addresses,
+                        left: Some(*va),
+                        right: Some(*vb),
+                    }),
+                    _ => (),
+                }
+            }
+        }
+        for (addresses, val) in self.uncovered_by(other) {
+            diffs.push(Difference {
+                addresses,
+                left: Some(val),
+                right: None,
+            });
+        }
+        for (addresses, val) in other.uncovered_by(self) {
+            diffs.push(Difference {
+                addresses,
+                left: None,
+                right: Some(val),
+            });
+        }
+        diffs.sort_unstable_by_key(|d| (d.addresses.floating, d.addresses.value));
+        diffs
+    }
+
+    // Parts of this memory's floating regions that `other` never wrote.
+    fn uncovered_by(&self, other: &Words) -> Vec<(Pattern, u64)> {
+        let mut uncovered = Vec::new();
+        for (pattern, val) in self.regions.iter() {
+            let covering = other
+                .regions
+                .iter()
+                .map(|(p, _)| *p)
+                .chain(
+                    other
+                        .concrete
+                        .keys()
+                        .filter(|addr| pattern.contains(**addr))
+                        .map(|addr| Pattern::single(*addr)),
+                )
+                .collect::<Vec<_>>();
+            let pieces = covering.iter().fold(vec![*pattern], |pieces, cover| {
+                pieces.iter().flat_map(|p| p.subtract(cover)).collect()
+            });
+            uncovered.extend(pieces.into_iter().map(|p| (p, *val)));
+        }
+        uncovered
+    }
+
+    // Sum of all values in memory, wrapping at 2^64.
+    pub fn sum(&self) -> u64 {
+        let concrete = self.concrete.values().fold(0u64, |a, v| a.wrapping_add(*v));
+        self.regions
+            .iter()
+            .map(|(p, val)| (p.address_count() * *val as u128) as u64)
+            .fold(concrete, u64::wrapping_add)
     }
 }
 
-impl Default for Memory {
+impl<D: Decoder + Default> Default for Memory<D> {
     fn default() -> Self {
-        Self::new()
+        Self::with_decoder(Default::default())
     }
 }
 
 impl Memory {
     pub fn new() -> Memory {
+        Default::default()
+    }
+}
+
+impl<D: Decoder> Memory<D> {
+    pub fn with_decoder(decoder: D) -> Memory<D> {
         Memory {
-            words: HashMap::new(),
+            words: Default::default(),
             mask: Mask::new(),
+            decoder,
         }
     }
 
-    pub fn apply(&mut self, i: &Input) {
+    pub fn apply(&mut self, i: &Instruction) {
         match i {
-            Input::WriteVal(w) => {
-                let val = w.val | self.mask.bits_set;
-                let val = val & self.mask.bits_cleared;
-                self.words.insert(w.addr, val);
-            }
-            Input::SetMask(m) => self.mask = m.clone(),
+            Instruction::SetMask(m) => self.mask = m.clone(),
+            Instruction::Write(w) => self.decoder.write(&self.mask, w, &mut self.words),
         };
     }
 
-    pub fn apply_v2(&mut self, i: &InputV2) {
-        match i {
-            InputV2::SetMask(m) => self.mask = m.clone(),
-            InputV2::WriteValV2(w) => {
-                for addr in self.mask.gen_addresses(w.addr) {
-                    self.words.insert(addr, w.val);
-                }
-            }
+    pub fn run(&mut self, program: &[Instruction]) {
+        for i in program.iter() {
+            self.apply(i);
         }
     }
 
     pub fn sum(&self) -> u64 {
-        self.words.iter().map(|(_, v)| v).sum()
+        self.words.sum()
+    }
+
+    pub fn get(&self, addr: u64) -> Option<u64> {
+        self.words.get(addr)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.words.iter()
+    }
+
+    pub fn words(&self) -> &Words {
+        &self.words
+    }
+
+    pub fn mask(&self) -> &Mask {
+        &self.mask
+    }
+
+    pub fn diff<E: Decoder>(&self, other: &Memory<E>) -> Vec<Difference> {
+        self.words.diff(&other.words)
+    }
+}
+
+// Snapshots use the same syntax as initialization programs: the current mask
+// followed by one `mem[...]` line per entry, where floating regions are
+// written as ternary patterns instead of decimal addresses.
+impl<D: Decoder> std::fmt::Display for Memory<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "mask = {}", self.mask)?;
+        for (pattern, val) in self.words.entries() {
+            if pattern.floating == 0 {
+                writeln!(f, "mem[{}] = {}", pattern.value, val)?;
+            } else {
+                writeln!(f, "mem[{}] = {}", pattern, val)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<D: Decoder + Default> std::str::FromStr for Memory<D> {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut memory: Memory<D> = Default::default();
+        for line in s.trim().lines() {
+            if line.starts_with("mask") {
+                memory.mask = line.parse::<Mask>()?;
+                continue;
+            }
+            let (addr, val) = scan_fmt!(line, "mem[{}] = {}", String, u64)
+                .map_err(|_| Error::WriteParseError(line.to_string()))?;
+            if addr.contains('X') {
+                memory.words.insert_pattern(addr.parse::<Pattern>()?, val);
+            } else {
+                let addr = addr
+                    .parse::<u64>()
+                    .map_err(|_| Error::WriteParseError(line.to_string()))?;
+                memory.words.insert(addr, val);
+            }
+        }
+        Ok(memory)
+    }
+}
+
+impl std::fmt::Display for Mask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let floating = self.floating_mask();
+        for bit in (0..width(self.bits_set | self.bits_cleared | floating)).rev() {
+            let c = match (floating >> bit & 1, self.bits_set >> bit & 1) {
+                (1, _) => 'X',
+                (_, 1) => '1',
+                _ => '0',
+            };
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write {
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    pub fn val(&self) -> u64 {
+        self.val
     }
 }
 
@@ -165,33 +589,26 @@ impl Mask {
             dont_cares: Vec::new(),
         }
     }
-    fn gen_addresses(&self, addr: u64) -> Vec<u64> {
-        (0..=2u32.pow(self.dont_cares.len() as u32) - 1)
-            .map(Self::bits_set)
-            .map(|set_idx| {
-                let mut addr = addr;
-                addr |= self.bits_set;
-                for (i, val) in self.dont_cares.iter().enumerate() {
-                    addr = match val {
-                        val if set_idx.contains(&i) => addr | (1 << val),
-                        val => addr & (!(1 << val)),
-                    };
-                }
-                addr
-            })
-            .collect::<Vec<_>>()
-    }
-    fn bits_set(mut i: u32) -> Vec<usize> {
-        let mut v = Vec::new();
-        let mut pos = 0;
-        while i != 0 {
-            if i & 1 == 1 {
-                v.push(pos);
-            }
-            i >>= 1;
-            pos += 1;
-        }
-        v
+
+    pub fn bits_set(&self) -> u64 {
+        self.bits_set
+    }
+
+    pub fn bits_cleared(&self) -> u64 {
+        self.bits_cleared
+    }
+
+    pub fn floating_bits(&self) -> &[usize] {
+        &self.dont_cares
+    }
+
+    pub fn floating_mask(&self) -> u64 {
+        self.dont_cares.iter().fold(0, |m, bit| m | (1 << bit))
+    }
+
+    // The addresses a version 2 chip writes to for `addr`.
+    pub fn address_pattern(&self, addr: u64) -> Pattern {
+        Pattern::new(addr | self.bits_set, self.floating_mask())
     }
 }
 
@@ -212,24 +629,171 @@ mem[26] = 1";
     #[test]
     fn test_writes() {
         let mut mem: Memory = Default::default();
-        let _ = TEST_INPUT
-            .trim()
-            .lines()
-            .map(|line| line.parse::<Input>().unwrap())
-            .map(|i| mem.apply(&i))
-            .collect::<Vec<_>>();
+        mem.run(
+            &TEST_INPUT
+                .trim()
+                .lines()
+                .map(|line| line.parse::<Instruction>().unwrap())
+                .collect::<Vec<_>>(),
+        );
         assert_eq!(165, mem.sum());
     }
 
     #[test]
     fn test_writes_v2() {
-        let mut mem: Memory = Default::default();
-        let _ = TEST_INPUT_V2
-            .trim()
-            .lines()
-            .map(|line| line.parse::<InputV2>().unwrap())
-            .map(|i| mem.apply_v2(&i))
-            .collect::<Vec<_>>();
+        let mut mem = Memory::with_decoder(DecoderV2);
+        mem.run(
+            &TEST_INPUT_V2
+                .trim()
+                .lines()
+                .map(|line| line.parse::<Instruction>().unwrap())
+                .collect::<Vec<_>>(),
+        );
         assert_eq!(208, mem.sum());
     }
+
+    // Writes the value unmasked to every address the mask could match.
+    struct Broadcast;
+
+    impl Decoder for Broadcast {
+        fn write(&self, mask: &Mask, write: &Write, words: &mut Words) {
+            for addr in mask.address_pattern(0).addresses() {
+                words.insert(addr | write.addr(), write.val());
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_decoder() {
+        let mut mem = Memory::with_decoder(Broadcast);
+        mem.apply(
+            &"mask = 0000000000000000000000000000000000X1"
+                .parse()
+                .unwrap(),
+        );
+        mem.apply(&"mem[8] = 3".parse().unwrap());
+        assert_eq!(6, mem.sum());
+    }
+
+    #[test]
+    fn test_pattern_subtract() {
+        let a = Pattern::new(0b0000, 0b1011);
+        let b = Pattern::new(0b0010, 0b0001);
+        let pieces = a.subtract(&b);
+        assert_eq!(
+            vec![Pattern::new(0b0000, 0b1001), Pattern::new(0b1010, 0b0001)],
+            pieces
+        );
+        assert_eq!(vec![0, 1, 8, 9], pieces[0].addresses().collect::<Vec<_>>());
+        assert_eq!(
+            a.address_count() - b.address_count(),
+            pieces.iter().map(|p| p.address_count()).sum()
+        );
+        assert!(pieces.iter().all(|p| !p.intersects(&b)));
+        assert_eq!(vec![a], a.subtract(&Pattern::new(0b0100, 0b0011)));
+    }
+
+    #[test]
+    fn test_many_floating_bits() {
+        let mut mem = Memory::with_decoder(DecoderV2);
+        mem.run(
+            &r"mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+mem[0] = 1
+mask = 0XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+mem[0] = 3
+mask = 000000000000000000000000000000000000
+mem[5] = 10"
+                .lines()
+                .map(|line| line.parse::<Instruction>().unwrap())
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!((1 << 35) + 3 * ((1 << 35) - 1) + 10, mem.sum());
+        assert_eq!(Some(10), mem.words.get(5));
+        assert_eq!(Some(3), mem.words.get(6));
+        assert_eq!(Some(1), mem.words.get(1 << 35));
+        assert_eq!(None, mem.words.get(1 << 36));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut mem = Memory::with_decoder(DecoderV2);
+        mem.run(
+            &TEST_INPUT_V2
+                .trim()
+                .lines()
+                .map(|line| line.parse::<Instruction>().unwrap())
+                .collect::<Vec<_>>(),
+        );
+        mem.apply(
+            &"mask = 000000000000000000000000000000000000"
+                .parse()
+                .unwrap(),
+        );
+        mem.apply(&"mem[3] = 9".parse().unwrap());
+        let snapshot = mem.to_string();
+        assert_eq!(
+            r"mask = 000000000000000000000000000000000000
+mem[3] = 9
+mem[00000000000000000000000000000011101X] = 100
+mem[00000000000000000000000000000001X0XX] = 1
+",
+            snapshot
+        );
+        let restored = snapshot.parse::<Memory<DecoderV2>>().unwrap();
+        assert_eq!(snapshot, restored.to_string());
+        assert_eq!(mem.sum(), restored.sum());
+        assert!(mem.diff(&restored).is_empty());
+        assert_eq!(
+            vec![(3, 9), (58, 100), (59, 100), (16, 1), (17, 1)],
+            restored.iter().take(5).collect::<Vec<_>>()
+        );
+        assert_eq!(Some(100), restored.get(59));
+        assert_eq!(None, restored.get(20));
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut left = Memory::with_decoder(DecoderV2);
+        left.apply(
+            &"mask = 000000000000000000000000000000X1001X"
+                .parse()
+                .unwrap(),
+        );
+        left.apply(&"mem[42] = 100".parse().unwrap());
+        let mut right: Memory<DecoderV2> = left.to_string().parse().unwrap();
+        right.apply(&"mem[58] = 7".parse().unwrap());
+        right.apply(
+            &"mask = 000000000000000000000000000000000000"
+                .parse()
+                .unwrap(),
+        );
+        right.apply(&"mem[26] = 100".parse().unwrap());
+        right.apply(
+            &"mask = 0000000000000000000000000000000000XX"
+                .parse()
+                .unwrap(),
+        );
+        right.apply(&"mem[0] = 5".parse().unwrap());
+
+        assert_eq!(
+            vec![
+                Difference {
+                    addresses: Pattern::single(58),
+                    left: Some(100),
+                    right: Some(7),
+                },
+                Difference {
+                    addresses: "XX".parse().unwrap(),
+                    left: None,
+                    right: Some(5),
+                },
+                Difference {
+                    addresses: Pattern::new(27, 32),
+                    left: Some(100),
+                    right: Some(7),
+                },
+            ],
+            left.diff(&right)
+        );
+    }
 }
@@ -1,6 +1,8 @@
+use crate::animation::Frame;
+use crate::animation::Render;
+use crate::automaton::Automaton;
 use itertools::Itertools;
-use ndarray::{arr1, arr2};
-use std::convert::TryFrom;
+use std::collections::HashMap;
 use thiserror::Error as ThisError;
 
 #[derive(Debug, ThisError)]
@@ -14,7 +16,7 @@ pub enum Error {
     #[error("failed to parse instruction")]
     InstructionFormatError {
         #[from]
-        source: std::num::ParseIntError,
+        source: std::num::ParseFloatError,
     },
 
     #[error("malformed instruction: '{0}'")]
@@ -30,9 +32,33 @@ enum Seat {
     Occupied,
 }
 
+// Which seats a passenger looks at when deciding whether to sit down or get
+// up.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Neighborhood {
+    // The eight positions around them.
+    Adjacent,
+    // The first seat in each of the eight directions, looking past floor for
+    // at most `range` positions if given.
+    LineOfSight { range: Option<usize> },
+    // The positions at these (row, column) offsets.
+    Offsets(Vec<(i32, i32)>),
+}
+
+// A passenger sits in an empty seat when none of the seats they can see are
+// taken, and leaves once `tolerance` of them are. With `wrap`, looking off one
+// edge of the area continues from the opposite edge.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SeatingRules {
+    pub neighborhood: Neighborhood,
+    pub tolerance: usize,
+    pub wrap: bool,
+}
+
 #[derive(Clone, Eq, PartialEq)]
 pub struct WaitingArea {
     seats: Vec<Vec<Seat>>,
+    rules: SeatingRules,
 }
 
 impl std::str::FromStr for Seat {
@@ -68,29 +94,71 @@ impl std::str::FromStr for WaitingArea {
                     .collect::<Result<Vec<_>>>()
             })
             .collect::<Result<Vec<_>>>()?;
-        Ok(WaitingArea { seats })
+        Ok(WaitingArea {
+            seats,
+            rules: SeatingRules::adjacent(),
+        })
     }
 }
 
 impl WaitingArea {
-    pub fn stabilize_adjacent(&self) -> WaitingArea {
-        let mut old = self.clone();
-        let mut new = old.step_adjacent();
-        while old != new {
-            old = new;
-            new = old.step_adjacent();
+    pub fn rules(mut self, rules: SeatingRules) -> WaitingArea {
+        self.rules = rules;
+        self
+    }
+
+    pub fn simulation(&self) -> Simulation {
+        Simulation::new(self)
+    }
+
+    // Steps until the seats stop changing, or until they start repeating if
+    // they never settle, and returns the first repeated arrangement.
+    pub fn stabilize(&self) -> WaitingArea {
+        self.stabilize_with(|_| ())
+    }
+
+    // Like `stabilize`, but shows `on_step` every generation up to the one
+    // that repeats, e.g. to record them with an `animation::Recorder`.
+    pub fn stabilize_with(&self, mut on_step: impl FnMut(&Simulation)) -> WaitingArea {
+        let mut simulation = self.simulation();
+        // Generations by hash; more than one on a collision.
+        let mut seen: HashMap<u64, Vec<usize>> = HashMap::new();
+        seen.insert(simulation.hash, vec![0]);
+        on_step(&simulation);
+        while simulation.step() > 0 {
+            on_step(&simulation);
+            let generations = seen.entry(simulation.hash).or_default();
+            // Oscillating if an earlier generation really had the same seats,
+            // so go back to where the cycle started.
+            if let Some(first) = generations
+                .iter()
+                .map(|&g| self.replay(g))
+                .find(|earlier| earlier.occupied == simulation.occupied)
+            {
+                simulation = first;
+                break;
+            }
+            generations.push(simulation.generation());
         }
-        new
+        simulation.waiting_area()
     }
 
-    pub fn stabilize_first_visible(&self) -> WaitingArea {
-        let mut old = self.clone();
-        let mut new = old.step_first_visible();
-        while old != new {
-            old = new;
-            new = old.step_first_visible();
+    fn replay(&self, generation: usize) -> Simulation {
+        let mut simulation = self.simulation();
+        for _ in 0..generation {
+            simulation.step();
         }
-        new
+        simulation
+    }
+
+    pub fn stabilize_adjacent(&self) -> WaitingArea {
+        self.clone().rules(SeatingRules::adjacent()).stabilize()
+    }
+
+    pub fn stabilize_first_visible(&self) -> WaitingArea {
+        self.clone()
+            .rules(SeatingRules::first_visible())
+            .stabilize()
     }
 
     pub fn num_occupied(&self) -> usize {
@@ -107,63 +175,85 @@ impl WaitingArea {
             .sum()
     }
 
-    fn step_adjacent(&self) -> WaitingArea {
-        let mut new = self.clone();
-        let _ = (0..self.seats.len())
-            .map(|row| {
-                (0..self.seats[row].len())
-                    .map(|col| {
-                        let surrounding_seats = (-1..=1)
-                            .cartesian_product(-1..=1)
-                            .filter(|&coord| coord != (0, 0))
-                            .map(|(delta_row, delta_col)| {
-                                self.get_seat(usize_add(row, delta_row), usize_add(col, delta_col))
-                            })
-                            .filter_map(Result::ok);
-                        let num_occupied =
-                            surrounding_seats.filter(|&s| s == &Seat::Occupied).count();
-                        let new_seat = match self.get_seat(row, col).unwrap() {
-                            Seat::Floor => Seat::Floor,
-                            Seat::Occupied if num_occupied >= 4 => Seat::Empty,
-                            Seat::Empty if num_occupied == 0 => Seat::Occupied,
-                            old_seat => old_seat.clone(),
-                        };
-                        new.set_seat(row, col, new_seat);
-                    })
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
-        new
+    // Where looking from (row, col) by (delta_row, delta_col) lands, if that
+    // is still in the area. Rows may differ in length, so columns are checked
+    // against the row landed on.
+    fn offset(&self, (row, col): (usize, usize), (dr, dc): (i32, i32)) -> Option<(usize, usize)> {
+        let height = self.seats.len() as i64;
+        let row = row as i64 + dr as i64;
+        let row = match self.rules.wrap {
+            true => row.rem_euclid(height),
+            false if (0..height).contains(&row) => row,
+            false => return None,
+        };
+        let width = self.seats[row as usize].len() as i64;
+        let col = col as i64 + dc as i64;
+        let col = match self.rules.wrap {
+            true if width > 0 => col.rem_euclid(width),
+            _ if (0..width).contains(&col) => col,
+            _ => return None,
+        };
+        Some((row as usize, col as usize))
+    }
+
+    // Every other seat the passenger at (row, col) can see, each once even if
+    // wrapping around makes it visible in more than one direction.
+    fn visible(&self, row: usize, col: usize) -> Vec<(usize, usize)> {
+        let compass = || {
+            (-1..=1)
+                .cartesian_product(-1..=1)
+                .filter(|&delta| delta != (0, 0))
+        };
+        let start = (row, col);
+        let seen: Vec<_> = match &self.rules.neighborhood {
+            Neighborhood::Adjacent => compass()
+                .filter_map(|delta| self.offset(start, delta))
+                .filter(|&(r, c)| self.get_seat(r, c).is_ok_and(|s| s != &Seat::Floor))
+                .collect(),
+            Neighborhood::Offsets(offsets) => offsets
+                .iter()
+                .filter_map(|&delta| self.offset(start, delta))
+                .filter(|&(r, c)| self.get_seat(r, c).is_ok_and(|s| s != &Seat::Floor))
+                .collect(),
+            Neighborhood::LineOfSight { range } => compass()
+                .filter_map(|delta| {
+                    let mut pos = start;
+                    // Wrapping around may lead back to where we started.
+                    for _ in 0..range.unwrap_or(usize::MAX) {
+                        pos = self.offset(pos, delta).filter(|&p| p != start)?;
+                        if self.get_seat(pos.0, pos.1).ok()? != &Seat::Floor {
+                            return Some(pos);
+                        }
+                    }
+                    None
+                })
+                .collect(),
+        };
+        seen.into_iter().filter(|&p| p != start).unique().collect()
+    }
+
+    fn next_seat(&self, row: usize, col: usize) -> Seat {
+        let seat = self.get_seat(row, col).unwrap();
+        if seat == &Seat::Floor {
+            return Seat::Floor;
+        }
+        let num_occupied = self
+            .visible(row, col)
+            .into_iter()
+            .filter(|&(r, c)| self.seats[r][c] == Seat::Occupied)
+            .count();
+        match seat {
+            Seat::Occupied if num_occupied >= self.rules.tolerance => Seat::Empty,
+            Seat::Empty if num_occupied == 0 => Seat::Occupied,
+            old_seat => old_seat.clone(),
+        }
     }
 
-    fn step_first_visible(&self) -> WaitingArea {
+    fn step(&self) -> WaitingArea {
         let mut new = self.clone();
-        let directions = (-1..=1)
-            .cartesian_product(-1..=1)
-            .filter(|&coord| coord != (0, 0))
-            .collect::<Vec<_>>();
         for row in 0..self.seats.len() {
             for col in 0..self.seats[row].len() {
-                let mut seats = Vec::new();
-                for dir in &directions {
-                    let mut coords = (usize_add(row, dir.0), usize_add(col, dir.1));
-                    let mut next = self.get_seat(coords.0, coords.1);
-                    while next.is_ok() && next.as_ref().unwrap() == &&Seat::Floor {
-                        coords = (usize_add(coords.0, dir.0), usize_add(coords.1, dir.1));
-                        next = self.get_seat(coords.0, coords.1);
-                    }
-                    if let Ok(s) = next {
-                        seats.push(s);
-                    }
-                }
-                let num_occupied = seats.iter().filter(|&&s| s == &Seat::Occupied).count();
-                let new_seat = match self.get_seat(row, col).unwrap() {
-                    Seat::Floor => Seat::Floor,
-                    Seat::Occupied if num_occupied >= 5 => Seat::Empty,
-                    Seat::Empty if num_occupied == 0 => Seat::Occupied,
-                    old_seat => old_seat.clone(),
-                };
-                new.set_seat(row, col, new_seat);
+                new.set_seat(row, col, self.next_seat(row, col));
             }
         }
         new
@@ -181,34 +271,279 @@ impl WaitingArea {
     }
 }
 
-fn usize_add(u: usize, i: i32) -> usize {
-    if i < 0 && (-i as usize) > u {
-        usize::MAX
-    } else {
-        usize::try_from(i32::try_from(u).unwrap() + i as i32).unwrap()
+impl Automaton for WaitingArea {
+    // Floor never changes, so which seats are taken is enough.
+    type Key = Vec<bool>;
+
+    fn key(&self) -> Self::Key {
+        self.seats
+            .iter()
+            .flatten()
+            .map(|s| s == &Seat::Occupied)
+            .collect()
+    }
+
+    fn step(&self) -> Self {
+        WaitingArea::step(self)
+    }
+}
+
+impl Render for WaitingArea {
+    fn render(&self) -> Frame {
+        let rows = self.seats.iter().map(|row| {
+            row.iter()
+                .map(|seat| match seat {
+                    Seat::Floor => '.',
+                    Seat::Empty => 'L',
+                    Seat::Occupied => '#',
+                })
+                .collect()
+        });
+        Frame::new(rows.collect())
+    }
+}
+
+impl SeatingRules {
+    // The eight seats around a passenger; four taken makes them leave.
+    pub fn adjacent() -> SeatingRules {
+        SeatingRules {
+            neighborhood: Neighborhood::Adjacent,
+            tolerance: 4,
+            wrap: false,
+        }
+    }
+
+    // The first seat in each of the eight directions; five taken makes them
+    // leave.
+    pub fn first_visible() -> SeatingRules {
+        SeatingRules {
+            neighborhood: Neighborhood::LineOfSight { range: None },
+            tolerance: 5,
+            wrap: false,
+        }
+    }
+}
+
+impl Default for SeatingRules {
+    fn default() -> Self {
+        Self::adjacent()
+    }
+}
+
+// Turns are in degrees and need not be right angles.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction {
+    North(f64),
+    South(f64),
+    East(f64),
+    West(f64),
+    Left(f64),
+    Right(f64),
+    Forward(f64),
+}
+
+// Steps a waiting area by only looking again at seats that can see one that
+// changed in the last step. Each seat's neighbors are found once up front,
+// and the number of occupied ones kept up to date as seats change.
+pub struct Simulation {
+    area: WaitingArea,
+    positions: Vec<(usize, usize)>,
+    occupied: Vec<bool>,
+    counts: Vec<usize>,
+    // The seats that can see each seat, as ranges into `watchers`.
+    starts: Vec<usize>,
+    watchers: Vec<usize>,
+    dirty: Vec<usize>,
+    queued: Vec<bool>,
+    // Zobrist hash of the occupied seats, to notice oscillation cheaply.
+    hash: u64,
+    generation: usize,
+}
+
+impl Simulation {
+    fn new(area: &WaitingArea) -> Simulation {
+        let mut index = HashMap::new();
+        let mut positions = Vec::new();
+        for (row, seats) in area.seats.iter().enumerate() {
+            for (col, seat) in seats.iter().enumerate() {
+                if seat != &Seat::Floor {
+                    index.insert((row, col), positions.len());
+                    positions.push((row, col));
+                }
+            }
+        }
+        let mut watching = vec![Vec::new(); positions.len()];
+        for (i, &(row, col)) in positions.iter().enumerate() {
+            for seen in area.visible(row, col) {
+                watching[index[&seen]].push(i);
+            }
+        }
+        let mut starts = vec![0];
+        for w in watching.iter() {
+            starts.push(starts.last().unwrap() + w.len());
+        }
+        let occupied = positions
+            .iter()
+            .map(|&(r, c)| area.seats[r][c] == Seat::Occupied)
+            .collect::<Vec<_>>();
+        let mut counts = vec![0; positions.len()];
+        let mut hash = 0;
+        for (i, _) in occupied.iter().enumerate().filter(|(_, &o)| o) {
+            for &w in watching[i].iter() {
+                counts[w] += 1;
+            }
+            hash ^= zobrist(i);
+        }
+        Simulation {
+            area: area.clone(),
+            dirty: (0..positions.len()).collect(),
+            queued: vec![true; positions.len()],
+            positions,
+            occupied,
+            counts,
+            starts,
+            watchers: watching.into_iter().flatten().collect(),
+            hash,
+            generation: 0,
+        }
+    }
+
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    // Returns how many seats changed.
+    pub fn step(&mut self) -> usize {
+        let tolerance = self.area.rules.tolerance;
+        let changed = self
+            .dirty
+            .iter()
+            .copied()
+            .filter(|&i| match self.occupied[i] {
+                true => self.counts[i] >= tolerance,
+                false => self.counts[i] == 0,
+            })
+            .collect::<Vec<_>>();
+        for &i in self.dirty.iter() {
+            self.queued[i] = false;
+        }
+        self.dirty.clear();
+        for &i in changed.iter() {
+            self.occupied[i] = !self.occupied[i];
+            self.hash ^= zobrist(i);
+            let watchers = &self.watchers[self.starts[i]..self.starts[i + 1]];
+            for &w in watchers.iter().chain(Some(&i)) {
+                if w != i {
+                    match self.occupied[i] {
+                        true => self.counts[w] += 1,
+                        false => self.counts[w] -= 1,
+                    }
+                }
+                if !self.queued[w] {
+                    self.queued[w] = true;
+                    self.dirty.push(w);
+                }
+            }
+        }
+        self.generation += 1;
+        changed.len()
+    }
+
+    pub fn waiting_area(&self) -> WaitingArea {
+        let mut area = self.area.clone();
+        for (&(row, col), &occupied) in self.positions.iter().zip(self.occupied.iter()) {
+            area.seats[row][col] = match occupied {
+                true => Seat::Occupied,
+                false => Seat::Empty,
+            };
+        }
+        area
+    }
+}
+
+impl Render for Simulation {
+    fn render(&self) -> Frame {
+        self.waiting_area().render()
     }
 }
 
-enum Instruction {
-    North(i32),
-    South(i32),
-    East(i32),
-    West(i32),
-    Left(i32),
-    Right(i32),
-    Forward(i32),
+// A fixed pseudo-random key per seat, from SplitMix64.
+fn zobrist(i: usize) -> u64 {
+    let mut z = (i as u64)
+        .wrapping_add(1)
+        .wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
 }
 
 pub struct Instructions(Vec<Instruction>);
 
+// Follows navigation instructions, keeping track of where it has been.
+// Positions are (east, north), and turns go clockwise for positive degrees.
+pub trait Navigator {
+    fn position(&self) -> (f64, f64);
+    // For North, South, East and West.
+    fn shift(&mut self, delta: (f64, f64));
+    fn turn(&mut self, degrees: f64);
+    fn forward(&mut self, distance: f64);
+
+    fn step(&mut self, i: &Instruction) {
+        match *i {
+            Instruction::North(num) => self.shift((0.0, num)),
+            Instruction::South(num) => self.shift((0.0, -num)),
+            Instruction::East(num) => self.shift((num, 0.0)),
+            Instruction::West(num) => self.shift((-num, 0.0)),
+            Instruction::Left(num) => self.turn(-num),
+            Instruction::Right(num) => self.turn(num),
+            Instruction::Forward(num) => self.forward(num),
+        }
+    }
+
+    // Returns the position before the first instruction and after each one.
+    fn mov(&mut self, instructions: &Instructions) -> Vec<(f64, f64)> {
+        let mut path = vec![self.position()];
+        for i in instructions.0.iter() {
+            self.step(i);
+            path.push(self.position());
+        }
+        path
+    }
+
+    fn distance_from_origin(&self) -> i32 {
+        let (east, north) = self.position();
+        (east.abs() + north.abs()).round() as i32
+    }
+}
+
 pub struct Ferry {
-    position: (i32, i32),
-    orientation: i32,
+    position: (f64, f64),
+    // Degrees clockwise from east.
+    heading: f64,
 }
 
 pub struct FerryAndWaypoint {
-    ferry_position: (i32, i32),
-    waypoint_position: ndarray::Array1<i32>,
+    ferry_position: (f64, f64),
+    // Relative to the ferry.
+    waypoint_position: (f64, f64),
+}
+
+// Turns `(east, north)` clockwise. Right angles are done exactly, so that
+// integer instructions keep integer positions.
+fn rotate((east, north): (f64, f64), degrees: f64) -> (f64, f64) {
+    let degrees = degrees.rem_euclid(360.0);
+    if degrees == 0.0 {
+        (east, north)
+    } else if degrees == 90.0 {
+        (north, -east)
+    } else if degrees == 180.0 {
+        (-east, -north)
+    } else if degrees == 270.0 {
+        (-north, east)
+    } else {
+        let (sin, cos) = (-degrees).to_radians().sin_cos();
+        (east * cos - north * sin, east * sin + north * cos)
+    }
 }
 
 impl std::str::FromStr for Instruction {
@@ -219,8 +554,10 @@ impl std::str::FromStr for Instruction {
             s.chars()
                 .next()
                 .ok_or_else(|| Error::InstructionParseError(s.to_string()))?,
-            s[1..].parse::<i32>()?,
+            s[1..].parse::<f64>()?,
         ) {
+            // `f64` parsing also accepts "NaN" and "inf", which aren't moves.
+            (_, num) if !num.is_finite() => Err(Error::InstructionParseError(s.to_string())),
             ('N', num) => Ok(Instruction::North(num)),
             ('S', num) => Ok(Instruction::South(num)),
             ('E', num) => Ok(Instruction::East(num)),
@@ -270,42 +607,32 @@ impl Default for Ferry {
 impl Ferry {
     pub fn new() -> Ferry {
         Ferry {
-            orientation: 0,
-            position: (0, 0),
+            heading: 0.0,
+            position: (0.0, 0.0),
         }
     }
-    pub fn mov(&mut self, instructions: &Instructions) {
-        let _ = instructions
-            .0
-            .iter()
-            .map(|i| self.step(i))
-            .collect::<Vec<_>>();
+
+    pub fn heading(&self) -> f64 {
+        self.heading
     }
+}
 
-    fn step(&mut self, i: &Instruction) {
-        match i {
-            Instruction::North(num) => self.position.1 += num,
-            Instruction::South(num) => self.position.1 -= num,
-            Instruction::East(num) => self.position.0 += num,
-            Instruction::West(num) => self.position.0 -= num,
-            Instruction::Forward(num) => {
-                self.position = (
-                    self.position.0 + self.orientation_vector().0 * num,
-                    self.position.1 + self.orientation_vector().1 * num,
-                )
-            }
-            Instruction::Left(num) => self.orientation = (self.orientation - num).rem_euclid(360),
-            Instruction::Right(num) => self.orientation = (self.orientation + num).rem_euclid(360),
-        }
+impl Navigator for Ferry {
+    fn position(&self) -> (f64, f64) {
+        self.position
     }
 
-    pub fn distance_from_origin(&self) -> i32 {
-        self.position.0.abs() + self.position.1.abs()
+    fn shift(&mut self, (east, north): (f64, f64)) {
+        self.position = (self.position.0 + east, self.position.1 + north);
     }
 
-    fn orientation_vector(&self) -> (i32, i32) {
-        static ORIENTATION_VEC: &[(i32, i32)] = &[(1, 0), (0, -1), (-1, 0), (0, 1)];
-        ORIENTATION_VEC[((self.orientation / 90).rem_euclid(4)) as usize]
+    fn turn(&mut self, degrees: f64) {
+        self.heading = (self.heading + degrees).rem_euclid(360.0);
+    }
+
+    fn forward(&mut self, distance: f64) {
+        let (east, north) = rotate((distance, 0.0), self.heading);
+        self.shift((east, north));
     }
 }
 
@@ -318,64 +645,41 @@ impl Default for FerryAndWaypoint {
 impl FerryAndWaypoint {
     pub fn new() -> FerryAndWaypoint {
         FerryAndWaypoint {
-            ferry_position: (0, 0),
-            waypoint_position: arr1(&[10, 1]),
+            ferry_position: (0.0, 0.0),
+            waypoint_position: (10.0, 1.0),
         }
     }
 
-    pub fn mov(&mut self, instructions: &Instructions) {
-        let _ = instructions
-            .0
-            .iter()
-            .map(|i| self.step(i))
-            .collect::<Vec<_>>();
+    pub fn waypoint(&self) -> (f64, f64) {
+        self.waypoint_position
+    }
+}
+
+impl Navigator for FerryAndWaypoint {
+    fn position(&self) -> (f64, f64) {
+        self.ferry_position
     }
 
-    fn step(&mut self, i: &Instruction) {
-        let right_rotations = [
-            arr2(&[[0, 1], [1, 0]]),
-            arr2(&[[0, -1], [1, 0]]),
-            arr2(&[[-1, 0], [0, -1]]),
-            arr2(&[[0, 1], [-1, 0]]),
-        ];
-        let left_rotations = [
-            arr2(&[[0, 1], [1, 0]]),
-            arr2(&[[0, 1], [-1, 0]]),
-            arr2(&[[-1, 0], [0, -1]]),
-            arr2(&[[0, -1], [1, 0]]),
-        ];
-        match i {
-            Instruction::North(num) => self.waypoint_position[1] += num,
-            Instruction::South(num) => self.waypoint_position[1] -= num,
-            Instruction::East(num) => self.waypoint_position[0] += num,
-            Instruction::West(num) => self.waypoint_position[0] -= num,
-            Instruction::Forward(num) => {
-                self.ferry_position = (
-                    self.ferry_position.0 + self.waypoint_position[0] * num,
-                    self.ferry_position.1 + self.waypoint_position[1] * num,
-                )
-            }
-            Instruction::Left(num) => {
-                self.waypoint_position = self
-                    .waypoint_position
-                    .dot(&left_rotations[(num / 90).rem_euclid(4) as usize])
-            }
-            Instruction::Right(num) => {
-                self.waypoint_position = self
-                    .waypoint_position
-                    .dot(&right_rotations[(num / 90).rem_euclid(4) as usize])
-            }
-        }
+    fn shift(&mut self, (east, north): (f64, f64)) {
+        let waypoint = self.waypoint_position;
+        self.waypoint_position = (waypoint.0 + east, waypoint.1 + north);
+    }
+
+    fn turn(&mut self, degrees: f64) {
+        self.waypoint_position = rotate(self.waypoint_position, degrees);
     }
 
-    pub fn distance_from_origin(&self) -> i32 {
-        self.ferry_position.0.abs() + self.ferry_position.1.abs()
+    fn forward(&mut self, times: f64) {
+        let (ferry, waypoint) = (self.ferry_position, self.waypoint_position);
+        self.ferry_position = (ferry.0 + waypoint.0 * times, ferry.1 + waypoint.1 * times);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::animation::Recorder;
+    use crate::automaton::Driver;
 
     static SEAT_INPUT: &str = r"L.LL.LL.LL
 LLLLLLL.LL
@@ -408,6 +712,204 @@ F11";
         assert_eq!(26, waiting_area.num_occupied());
     }
 
+    #[test]
+    fn test_stabilize_cycle() {
+        let waiting_area = SEAT_INPUT.parse::<WaitingArea>().unwrap();
+        let cycle = Driver::new(waiting_area.clone()).run_until_cycle();
+        assert!(cycle.is_fixed_point());
+        assert_eq!(5, cycle.pre_period);
+        let waiting_area = waiting_area.rules(SeatingRules::first_visible());
+        let cycle = Driver::new(waiting_area).run_until_cycle();
+        assert_eq!((6, 1), (cycle.pre_period, cycle.period));
+    }
+
+    #[test]
+    fn test_seating_rules() {
+        let waiting_area = "L.L\n...\nL.L".parse::<WaitingArea>().unwrap();
+        let diagonal = waiting_area.visible(0, 0);
+        assert!(diagonal.is_empty());
+        let waiting_area = waiting_area.rules(SeatingRules::first_visible());
+        let mut seen = waiting_area.visible(0, 0);
+        seen.sort_unstable();
+        assert_eq!(vec![(0, 2), (2, 0), (2, 2)], seen);
+        let waiting_area = waiting_area.rules(SeatingRules {
+            neighborhood: Neighborhood::LineOfSight { range: Some(1) },
+            ..SeatingRules::first_visible()
+        });
+        assert!(waiting_area.visible(0, 0).is_empty());
+
+        // On a torus every seat of a 3x3 all-seat area sees all the others,
+        // so everyone sits down at once, then all leave, and so on.
+        let rules = SeatingRules {
+            wrap: true,
+            ..SeatingRules::adjacent()
+        };
+        let waiting_area = "LLL\nLLL\nLLL".parse::<WaitingArea>().unwrap().rules(rules);
+        assert_eq!(8, waiting_area.visible(1, 1).len());
+        assert_eq!(8, waiting_area.visible(0, 0).len());
+        let cycle = Driver::new(waiting_area.clone()).run_until_cycle();
+        assert_eq!((0, 2), (cycle.pre_period, cycle.period));
+
+        let knight = SeatingRules {
+            neighborhood: Neighborhood::Offsets(vec![(1, 2), (2, 1), (-1, 2)]),
+            tolerance: 1,
+            wrap: false,
+        };
+        let waiting_area = waiting_area.rules(knight);
+        assert_eq!(vec![(1, 2), (2, 1)], waiting_area.visible(0, 0));
+    }
+
+    #[test]
+    fn test_ragged_area() {
+        let waiting_area = "LL\nL".parse::<WaitingArea>().unwrap();
+        assert_eq!(3, waiting_area.stabilize_adjacent().num_occupied());
+        assert_eq!(vec![(0, 1), (1, 0)], waiting_area.visible(0, 0));
+        assert_eq!(vec![(0, 0), (1, 0)], waiting_area.visible(0, 1));
+        let wrapped = "LLL\n\nL."
+            .parse::<WaitingArea>()
+            .unwrap()
+            .rules(SeatingRules {
+                wrap: true,
+                ..SeatingRules::adjacent()
+            });
+        assert_eq!(stabilize_slowly(&wrapped).key(), wrapped.stabilize().key());
+    }
+
+    #[test]
+    fn test_wrap_degenerate() {
+        // Wrapping around areas this small brings passengers back to their
+        // own seat, and to the same neighbor from several directions.
+        let rules = [
+            SeatingRules {
+                wrap: true,
+                ..SeatingRules::adjacent()
+            },
+            SeatingRules {
+                wrap: true,
+                ..SeatingRules::first_visible()
+            },
+            SeatingRules {
+                neighborhood: Neighborhood::Offsets(vec![(0, 1), (0, 2), (1, 0), (1, 1)]),
+                tolerance: 1,
+                wrap: true,
+            },
+        ];
+        let single = "L".parse::<WaitingArea>().unwrap().rules(rules[0].clone());
+        assert!(single.visible(0, 0).is_empty());
+        assert_eq!(1, single.stabilize().num_occupied());
+        assert_eq!(1, stabilize_slowly(&single).num_occupied());
+        let pair = "LL".parse::<WaitingArea>().unwrap().rules(rules[0].clone());
+        assert_eq!(vec![(0, 1)], pair.visible(0, 0));
+
+        for area in ["L", "LL", "LLL", "L.L", "LL\nLL", "LLL\nL.L"].iter() {
+            for rules in rules.iter() {
+                let waiting_area = area.parse::<WaitingArea>().unwrap().rules(rules.clone());
+                assert_eq!(
+                    stabilize_slowly(&waiting_area).key(),
+                    waiting_area.stabilize().key(),
+                    "{:?} with {:?}",
+                    area,
+                    rules
+                );
+            }
+        }
+    }
+
+    // Roughly a fifth floor, and the rest empty seats.
+    fn random_area(height: usize, width: usize, mut state: u64) -> WaitingArea {
+        let mut lines = Vec::new();
+        for _ in 0..height {
+            let line = (0..width)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    if state % 5 == 0 {
+                        '.'
+                    } else {
+                        'L'
+                    }
+                })
+                .collect::<String>();
+            lines.push(line);
+        }
+        lines.join("\n").parse().unwrap()
+    }
+
+    fn stabilize_slowly(waiting_area: &WaitingArea) -> WaitingArea {
+        let mut driver = Driver::new(waiting_area.clone());
+        let cycle = driver.run_until_cycle();
+        driver.nth(cycle.pre_period)
+    }
+
+    #[test]
+    fn test_simulation() {
+        let waiting_area = SEAT_INPUT.parse::<WaitingArea>().unwrap();
+        let mut simulation = waiting_area.simulation();
+        assert_eq!(71, simulation.step());
+        assert_eq!(waiting_area.step().key(), simulation.waiting_area().key());
+        while simulation.step() > 0 {}
+        assert_eq!(6, simulation.generation());
+        assert_eq!(37, simulation.waiting_area().num_occupied());
+
+        let presets = vec![
+            SeatingRules::adjacent(),
+            SeatingRules::first_visible(),
+            SeatingRules {
+                wrap: true,
+                ..SeatingRules::first_visible()
+            },
+            SeatingRules {
+                neighborhood: Neighborhood::Offsets(vec![(0, 1), (1, 1), (2, 0)]),
+                tolerance: 2,
+                wrap: true,
+            },
+        ];
+        for (i, rules) in presets.into_iter().enumerate() {
+            let waiting_area = random_area(30, 40, 2020 + i as u64).rules(rules);
+            assert_eq!(
+                stabilize_slowly(&waiting_area).key(),
+                waiting_area.stabilize().key()
+            );
+        }
+    }
+
+    #[test]
+    fn test_record_stabilize() {
+        let waiting_area = SEAT_INPUT.parse::<WaitingArea>().unwrap();
+        let mut recorder = Recorder::new();
+        let stable = waiting_area.stabilize_with(|s| recorder.record(s));
+        let frames = recorder.frames();
+        assert_eq!(6, frames.len());
+        assert_eq!(format!("{}\n", SEAT_INPUT), frames[0].to_ascii());
+        assert_eq!(stable.render(), frames[5]);
+        assert!(frames[1].to_ascii().starts_with("#.##.##.##\n"));
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "expensive_tests"), ignore)]
+    fn bench_large_area() {
+        // Random areas this big tend to end up flickering rather than
+        // settling, which `stabilize` has to notice too.
+        let waiting_area = random_area(1000, 1000, 11);
+        for rules in [SeatingRules::adjacent(), SeatingRules::first_visible()].iter() {
+            let waiting_area = waiting_area.clone().rules(rules.clone());
+            let start = std::time::Instant::now();
+            let stable = waiting_area.stabilize();
+            println!("1000x1000, {:?}: {:?}", rules.neighborhood, start.elapsed());
+            let key = stable.key();
+            assert!(stable.step().key() == key || stable.step().step().key() == key);
+        }
+
+        let waiting_area = random_area(200, 200, 11);
+        let start = std::time::Instant::now();
+        let slow = stabilize_slowly(&waiting_area);
+        println!("200x200, every seat every step: {:?}", start.elapsed());
+        let start = std::time::Instant::now();
+        assert_eq!(slow.key(), waiting_area.stabilize().key());
+        println!("200x200, incremental: {:?}", start.elapsed());
+    }
+
     #[test]
     fn test_ferry_move() {
         let instructions = INSTRUCTIONS_INPUT.parse::<Instructions>().unwrap();
@@ -422,4 +924,50 @@ F11";
         ferry.mov(&instructions);
         assert_eq!(286, ferry.distance_from_origin());
     }
+
+    #[test]
+    fn test_navigator_path() {
+        let instructions = INSTRUCTIONS_INPUT.parse::<Instructions>().unwrap();
+        let path = Ferry::new().mov(&instructions);
+        assert_eq!(
+            vec![
+                (0.0, 0.0),
+                (10.0, 0.0),
+                (10.0, 3.0),
+                (17.0, 3.0),
+                (17.0, 3.0),
+                (17.0, -8.0)
+            ],
+            path
+        );
+        let mut ferry = FerryAndWaypoint::new();
+        let path = ferry.mov(&instructions);
+        assert_eq!((170.0, 38.0), path[3]);
+        assert_eq!((4.0, -10.0), ferry.waypoint());
+
+        // Off thirty degrees north of east.
+        let mut ferry = Ferry::new();
+        ferry.mov(&"R30\nL60\nF10\nR7.5".parse().unwrap());
+        assert_eq!(14, ferry.distance_from_origin());
+        assert!((ferry.position().0 - 8.66).abs() < 0.01);
+        assert!((ferry.position().1 - 5.0).abs() < 1e-9);
+        assert_eq!(337.5, ferry.heading());
+
+        let mut ferry = FerryAndWaypoint::new();
+        ferry.mov(&"L45\nF1".parse().unwrap());
+        let (east, north) = ferry.position();
+        assert!((east - 9.0 / 2f64.sqrt()).abs() < 1e-9);
+        assert!((north - 11.0 / 2f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_non_finite_instructions() {
+        for input in ["FNaN\nN3", "Finf", "R-infinity", "N1e400"].iter() {
+            assert!(matches!(
+                input.parse::<Instructions>(),
+                Err(Error::InstructionParseError(_))
+            ));
+        }
+        assert!("F1e3".parse::<Instructions>().is_ok());
+    }
 }
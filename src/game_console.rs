@@ -1,104 +1,746 @@
+use std::collections::HashMap;
 use std::collections::HashSet;
+use thiserror::Error as ThisError;
 
-#[derive(Clone)]
-enum OpCode {
+pub mod analysis;
+pub mod trace;
+
+#[derive(ThisError, Debug, PartialEq)]
+pub enum Error {
+    #[error("{line}:{column}: unrecognized opcode: {opcode}")]
+    UnknownOpCode {
+        line: usize,
+        column: usize,
+        opcode: String,
+    },
+
+    #[error("{line}:{column}: missing operand")]
+    MissingOperand { line: usize, column: usize },
+
+    #[error("{line}:{column}: can't parse operand: {operand}")]
+    InvalidOperand {
+        line: usize,
+        column: usize,
+        operand: String,
+    },
+
+    #[error("{line}:{column}: unexpected token: {token}")]
+    UnexpectedToken {
+        line: usize,
+        column: usize,
+        token: String,
+    },
+
+    #[error("{line}:{column}: invalid label: {label}")]
+    InvalidLabel {
+        line: usize,
+        column: usize,
+        label: String,
+    },
+
+    #[error("{line}:{column}: label defined more than once: {label}")]
+    DuplicateLabel {
+        line: usize,
+        column: usize,
+        label: String,
+    },
+
+    #[error("{line}:{column}: undefined label: {label}")]
+    UndefinedLabel {
+        line: usize,
+        column: usize,
+        label: String,
+    },
+
+    #[error("{line}:{column}: can't decode {opcode}: {error:?}")]
+    InvalidInstruction {
+        line: usize,
+        column: usize,
+        opcode: String,
+        error: DecodeError,
+    },
+
+    #[error("undefined label: {0}")]
+    UnknownLabel(String),
+
+    #[error("got {0} instructions; want exactly 1")]
+    InstructionCount(usize),
+
+    #[error("program already terminates")]
+    AlreadyTerminates,
+
+    #[error("no modification produces terminating program")]
+    NoTerminatingFix,
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+// An instruction set the VM can be instantiated with. The assembler hands
+// each mnemonic and its operands to `decode`, and the execution context calls
+// `execute` for every instruction, which may update the ISA-specific machine
+// state and decides where control flows next.
+pub trait InstructionSet {
+    type Instruction: Clone + std::fmt::Debug + PartialEq + std::fmt::Display;
+    type State: Clone + std::fmt::Debug + Default + PartialEq;
+
+    // Whether reaching an already-executed instruction means the program is
+    // stuck. Instruction sets with conditional jumps should turn this off.
+    const LOOP_ON_REVISIT: bool = true;
+
+    fn decode(
+        mnemonic: &str,
+        operands: &[Operand],
+    ) -> std::result::Result<Self::Instruction, DecodeError>;
+
+    fn execute(instruction: &Self::Instruction, state: &mut Self::State) -> Flow;
+}
+
+// Operands as seen by `InstructionSet::decode`. Names that match a label are
+// resolved to a relative offset before decoding; any other name (a register,
+// say) is passed through as a symbol for the instruction set to interpret.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand<'a> {
+    Value(i32),
+    Symbol(&'a str),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DecodeError {
+    UnknownOpCode,
+    OperandCount(usize),
+    InvalidOperand(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flow {
+    Next,
+    Jump(i32),
+    Halt,
+    // The instruction's arithmetic overflowed; the program stops on it with
+    // the state left as it was.
+    Overflow,
+}
+
+// The original handheld instruction set: nop, acc and jmp over a single i32
+// accumulator.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Handheld;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpCode {
     Nop(i32),
     Acc(i32),
     Jmp(i32),
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ExitReason {
     Loop,
     Terminate,
+    OutOfBounds(i32),
+    NegativeProgramCounter(i32),
+    StepLimit(usize),
+    // The instruction at this program counter overflowed.
+    Overflow(i32),
 }
 
-#[derive(Clone)]
-pub struct Program {
-    codes: Vec<OpCode>,
+// Instruction sets that don't stop on revisits can run forever, so every
+// context gives up after this many steps unless told otherwise.
+pub const DEFAULT_STEP_LIMIT: usize = 10_000_000;
+
+pub struct Program<I: InstructionSet = Handheld> {
+    codes: Vec<I::Instruction>,
+    labels: HashMap<String, i32>,
 }
 
-#[derive(Default)]
-pub struct ExecutionContext {
+pub struct ExecutionContext<I: InstructionSet = Handheld> {
     program_counter: i32,
-    accumulator: i32,
+    state: I::State,
     executed_instructions: HashSet<i32>,
+    steps: usize,
+    step_limit: usize,
+    trace: Option<trace::Trace<I>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Watch<S> {
+    StateChanged,
+    StateEquals(S),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stop<S> {
+    Step,
+    Breakpoint(i32),
+    Watch { watch: Watch<S>, old: S, new: S },
+    Exit(ExitReason),
+}
+
+pub struct Debugger<'a, I: InstructionSet = Handheld> {
+    program: &'a Program<I>,
+    ctx: ExecutionContext<I>,
+    breakpoints: HashSet<i32>,
+    watches: Vec<Watch<I::State>>,
+    exit: Option<ExitReason>,
+}
+
+struct Statement<'a> {
+    line: usize,
+    column: usize,
+    mnemonic: &'a str,
+    operands: Vec<(usize, &'a str, Operand<'a>)>,
+}
+
+// Splits a line into whitespace-separated tokens along with their 1-based
+// column, dropping anything after a ';' comment marker.
+fn tokenize(line: &str) -> Vec<(usize, &str)> {
+    let line = match line.find(';') {
+        Some(i) => &line[..i],
+        None => line,
+    };
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in line.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                tokens.push((s + 1, &line[s..i]));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => (),
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((s + 1, &line[s..]));
+    }
+    tokens
+}
+
+fn is_label(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+impl InstructionSet for Handheld {
+    type Instruction = OpCode;
+    type State = i32;
+
+    fn decode(mnemonic: &str, operands: &[Operand]) -> std::result::Result<OpCode, DecodeError> {
+        let make = match mnemonic {
+            "nop" => OpCode::Nop,
+            "acc" => OpCode::Acc,
+            "jmp" => OpCode::Jmp,
+            _ => return Err(DecodeError::UnknownOpCode),
+        };
+        match operands {
+            [Operand::Value(val)] => Ok(make(*val)),
+            [Operand::Symbol(_)] => Err(DecodeError::InvalidOperand(0)),
+            _ => Err(DecodeError::OperandCount(1)),
+        }
+    }
+
+    fn execute(instruction: &OpCode, accumulator: &mut i32) -> Flow {
+        match instruction {
+            OpCode::Nop(_) => Flow::Next,
+            OpCode::Acc(num) => match accumulator.checked_add(*num) {
+                Some(sum) => {
+                    *accumulator = sum;
+                    Flow::Next
+                }
+                None => Flow::Overflow,
+            },
+            OpCode::Jmp(num) => Flow::Jump(*num),
+        }
+    }
 }
 
 impl Program {
     pub fn new(s: &str) -> Program {
-        Program {
-            codes: s.trim().lines().map(OpCode::new).collect(),
+        Program::assemble(s).unwrap_or_else(|e| panic!("can't parse program: {}", e))
+    }
+
+    pub fn modify_to_terminate(&self) -> Result<Program> {
+        analysis::repair(self).map(|repair| repair.program)
+    }
+}
+
+impl<I: InstructionSet> Program<I> {
+    // Parses program source, which in addition to the plain `op +N` format
+    // accepts `name:` label definitions, label names as jump operands, and
+    // `;` comments.
+    pub fn assemble(s: &str) -> Result<Program<I>> {
+        let mut labels = HashMap::new();
+        let mut statements = Vec::new();
+        for (line_num, line) in s.lines().enumerate() {
+            let line_num = line_num + 1;
+            let mut tokens = &tokenize(line)[..];
+            if let Some(&(column, token)) = tokens.first() {
+                if let Some(label) = token.strip_suffix(':') {
+                    if !is_label(label) {
+                        return Err(Error::InvalidLabel {
+                            line: line_num,
+                            column,
+                            label: label.to_string(),
+                        });
+                    }
+                    if labels
+                        .insert(label.to_string(), statements.len() as i32)
+                        .is_some()
+                    {
+                        return Err(Error::DuplicateLabel {
+                            line: line_num,
+                            column,
+                            label: label.to_string(),
+                        });
+                    }
+                    tokens = &tokens[1..];
+                }
+            }
+            let (column, mnemonic) = match tokens.first() {
+                Some(&t) => t,
+                None => continue,
+            };
+            let operands = tokens[1..]
+                .iter()
+                .map(|&(column, operand)| {
+                    if is_label(operand) {
+                        Ok((column, operand, Operand::Symbol(operand)))
+                    } else {
+                        operand
+                            .parse::<i32>()
+                            .map(|val| (column, operand, Operand::Value(val)))
+                            .map_err(|_| Error::InvalidOperand {
+                                line: line_num,
+                                column,
+                                operand: operand.to_string(),
+                            })
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+            statements.push(Statement {
+                line: line_num,
+                column,
+                mnemonic,
+                operands,
+            });
+        }
+
+        let codes = statements
+            .iter()
+            .enumerate()
+            .map(|(i, statement)| statement.decode::<I>(i, &labels))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Program { codes, labels })
+    }
+
+    pub fn disassemble(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn len(&self) -> usize {
+        self.codes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+
+    pub fn get(&self, pc: i32) -> Option<&I::Instruction> {
+        if pc < 0 {
+            return None;
         }
+        self.codes.get(pc as usize)
+    }
+
+    pub fn label(&self, name: &str) -> Option<i32> {
+        self.labels.get(name).copied()
     }
+}
 
-    pub fn modify_to_terminate(&self) -> Program {
-        for i in 0..self.codes.len() {
-            let mut program = self.clone();
-            match program.codes[i] {
-                OpCode::Acc(_) => continue,
-                OpCode::Nop(val) => program.codes[i] = OpCode::Jmp(val),
-                OpCode::Jmp(val) => program.codes[i] = OpCode::Nop(val),
+impl<'a> Statement<'a> {
+    fn decode<I: InstructionSet>(
+        &self,
+        index: usize,
+        labels: &HashMap<String, i32>,
+    ) -> Result<I::Instruction> {
+        let operands = self
+            .operands
+            .iter()
+            .map(|(_, _, operand)| match operand {
+                Operand::Symbol(name) => match labels.get(*name) {
+                    Some(target) => Operand::Value(target - index as i32),
+                    None => *operand,
+                },
+                _ => *operand,
+            })
+            .collect::<Vec<_>>();
+        I::decode(self.mnemonic, &operands).map_err(|e| match e {
+            DecodeError::OperandCount(want) if want > self.operands.len() => {
+                Error::MissingOperand {
+                    line: self.line,
+                    column: match self.operands.last() {
+                        Some((column, text, _)) => column + text.len(),
+                        None => self.column + self.mnemonic.len(),
+                    },
+                }
             }
-            match ExecutionContext::new().run(&program) {
-                ExitReason::Loop => continue,
-                ExitReason::Terminate => return program,
+            DecodeError::OperandCount(want) if want < self.operands.len() => {
+                let (column, text, _) = self.operands[want];
+                Error::UnexpectedToken {
+                    line: self.line,
+                    column,
+                    token: text.to_string(),
+                }
             }
+            DecodeError::InvalidOperand(i) if i < self.operands.len() => match self.operands[i] {
+                (column, text, Operand::Symbol(_)) => Error::UndefinedLabel {
+                    line: self.line,
+                    column,
+                    label: text.to_string(),
+                },
+                (column, text, Operand::Value(_)) => Error::InvalidOperand {
+                    line: self.line,
+                    column,
+                    operand: text.to_string(),
+                },
+            },
+            DecodeError::UnknownOpCode => Error::UnknownOpCode {
+                line: self.line,
+                column: self.column,
+                opcode: self.mnemonic.to_string(),
+            },
+            _ => Error::InvalidInstruction {
+                line: self.line,
+                column: self.column,
+                opcode: self.mnemonic.to_string(),
+                error: e,
+            },
+        })
+    }
+}
+
+impl<I: InstructionSet> Clone for Program<I> {
+    fn clone(&self) -> Self {
+        Program {
+            codes: self.codes.clone(),
+            labels: self.labels.clone(),
         }
-        panic!("no modification produces terminating program");
+    }
+}
+
+impl<I: InstructionSet> std::fmt::Debug for Program<I> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Program")
+            .field("codes", &self.codes)
+            .field("labels", &self.labels)
+            .finish()
+    }
+}
+
+impl<I: InstructionSet> PartialEq for Program<I> {
+    fn eq(&self, other: &Self) -> bool {
+        self.codes == other.codes && self.labels == other.labels
+    }
+}
+
+impl<I: InstructionSet> std::str::FromStr for Program<I> {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Program::assemble(s)
     }
 }
 
 impl OpCode {
     pub fn new(s: &str) -> OpCode {
-        let fields = s.trim().split(' ').collect::<Vec<_>>();
-        let val = fields[1]
-            .parse::<i32>()
-            .unwrap_or_else(|_| panic!("can't parse: {}", fields[1]));
-        match fields[0] {
-            "nop" => OpCode::Nop(val),
-            "acc" => OpCode::Acc(val),
-            "jmp" => OpCode::Jmp(val),
-            _ => panic!("unrecognized opcode: {}", fields[0]),
+        s.parse::<OpCode>()
+            .unwrap_or_else(|e| panic!("can't parse opcode: {}", e))
+    }
+}
+
+impl std::str::FromStr for OpCode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let program = Program::<Handheld>::assemble(s)?;
+        match program.codes[..] {
+            [code] => Ok(code),
+            _ => Err(Error::InstructionCount(program.codes.len())),
+        }
+    }
+}
+
+impl std::fmt::Display for OpCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpCode::Nop(val) => write!(f, "nop {:+}", val),
+            OpCode::Acc(val) => write!(f, "acc {:+}", val),
+            OpCode::Jmp(val) => write!(f, "jmp {:+}", val),
+        }
+    }
+}
+
+impl<I: InstructionSet> std::fmt::Display for Program<I> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for code in self.codes.iter() {
+            writeln!(f, "{}", code)?;
         }
+        Ok(())
     }
 }
 
 impl ExecutionContext {
     pub fn new() -> ExecutionContext {
+        Default::default()
+    }
+
+    pub fn accumulator(&self) -> i32 {
+        self.state
+    }
+}
+
+impl<I: InstructionSet> Default for ExecutionContext<I> {
+    fn default() -> Self {
         ExecutionContext {
             program_counter: 0,
-            accumulator: 0,
+            state: Default::default(),
             executed_instructions: HashSet::new(),
+            steps: 0,
+            step_limit: DEFAULT_STEP_LIMIT,
+            trace: None,
         }
     }
+}
 
-    pub fn run(&mut self, program: &Program) -> ExitReason {
+impl<I: InstructionSet> ExecutionContext<I> {
+    pub fn run(&mut self, program: &Program<I>) -> ExitReason {
         loop {
-            self.executed_instructions.insert(self.program_counter);
-            match program.codes[self.program_counter as usize] {
-                OpCode::Nop(_) => self.program_counter += 1,
-                OpCode::Acc(num) => {
-                    self.accumulator += num;
-                    self.program_counter += 1;
-                }
-                OpCode::Jmp(num) => {
-                    self.program_counter += num;
-                }
+            if let Some(reason) = self.step(program) {
+                return reason;
             }
-            if self.executed_instructions.contains(&self.program_counter) {
-                return ExitReason::Loop;
+        }
+    }
+
+    // Executes a single instruction, returning the exit reason if the program
+    // has terminated or overflowed, the next instruction has already been
+    // executed or the step limit has been used up.
+    pub fn step(&mut self, program: &Program<I>) -> Option<ExitReason> {
+        let len = program.codes.len() as i32;
+        if let Some(reason) = self.bounds_check(len) {
+            return Some(reason);
+        }
+        if self.steps >= self.step_limit {
+            return Some(ExitReason::StepLimit(self.steps));
+        }
+        self.steps += 1;
+        let pc = self.program_counter;
+        let instruction = &program.codes[pc as usize];
+        let before = self.trace.as_ref().map(|_| self.state.clone());
+        self.executed_instructions.insert(pc);
+        let stopped = match I::execute(instruction, &mut self.state) {
+            Flow::Next => {
+                self.program_counter += 1;
+                None
             }
-            if self.program_counter < 0 {
-                panic!("program counter goes negative!");
+            Flow::Jump(offset) => {
+                self.program_counter = self.program_counter.saturating_add(offset);
+                None
             }
-            if self.program_counter >= program.codes.len() as i32 {
-                return ExitReason::Terminate;
+            Flow::Halt => {
+                self.program_counter = len;
+                Some(ExitReason::Terminate)
             }
+            Flow::Overflow => Some(ExitReason::Overflow(pc)),
+        };
+        if let (Some(trace), Some(before)) = (self.trace.as_mut(), before) {
+            trace.record(trace::TraceEntry {
+                pc,
+                instruction: instruction.clone(),
+                before,
+                after: self.state.clone(),
+                next: self.program_counter,
+            });
+        }
+        if stopped.is_some() {
+            return stopped;
         }
+        if I::LOOP_ON_REVISIT && self.executed_instructions.contains(&self.program_counter) {
+            return Some(ExitReason::Loop);
+        }
+        self.bounds_check(len)
+    }
+
+    // Only jumping to the instruction immediately after the last one counts as
+    // a clean termination.
+    fn bounds_check(&self, len: i32) -> Option<ExitReason> {
+        match self.program_counter {
+            pc if pc < 0 => Some(ExitReason::NegativeProgramCounter(pc)),
+            pc if pc == len => Some(ExitReason::Terminate),
+            pc if pc > len => Some(ExitReason::OutOfBounds(pc)),
+            _ => None,
+        }
+    }
+
+    // Starts recording every instruction executed from here on.
+    pub fn enable_tracing(&mut self) {
+        self.trace.get_or_insert_with(Default::default);
+    }
+
+    pub fn trace(&self) -> Option<&trace::Trace<I>> {
+        self.trace.as_ref()
+    }
+
+    pub fn take_trace(&mut self) -> Option<trace::Trace<I>> {
+        self.trace.take()
+    }
+
+    pub fn state(&self) -> &I::State {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut I::State {
+        &mut self.state
+    }
+
+    pub fn program_counter(&self) -> i32 {
+        self.program_counter
+    }
+
+    pub fn set_step_limit(&mut self, limit: usize) {
+        self.step_limit = limit;
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
     }
 
+    pub fn executed_instructions(&self) -> &HashSet<i32> {
+        &self.executed_instructions
+    }
+}
+
+impl<'a> Debugger<'a> {
     pub fn accumulator(&self) -> i32 {
-        self.accumulator
+        self.ctx.accumulator()
+    }
+}
+
+impl<'a, I: InstructionSet> Debugger<'a, I> {
+    pub fn new(program: &'a Program<I>) -> Debugger<'a, I> {
+        Debugger {
+            program,
+            ctx: Default::default(),
+            breakpoints: HashSet::new(),
+            watches: Vec::new(),
+            exit: None,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, pc: i32) {
+        self.breakpoints.insert(pc);
+    }
+
+    pub fn add_breakpoint_at_label(&mut self, label: &str) -> Result<()> {
+        let pc = self
+            .program
+            .label(label)
+            .ok_or_else(|| Error::UnknownLabel(label.to_string()))?;
+        self.add_breakpoint(pc);
+        Ok(())
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: i32) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    pub fn add_watch(&mut self, watch: Watch<I::State>) {
+        self.watches.push(watch);
+    }
+
+    pub fn clear_watches(&mut self) {
+        self.watches.clear();
+    }
+
+    pub fn step(&mut self) -> Stop<I::State> {
+        self.execute().unwrap_or(Stop::Step)
+    }
+
+    // Runs until a breakpoint is reached, a watch triggers or the program
+    // exits. Breakpoints stop before the instruction at that address runs.
+    pub fn cont(&mut self) -> Stop<I::State> {
+        self.resume(None)
+    }
+
+    pub fn run_until(&mut self, breakpoint: i32) -> Stop<I::State> {
+        self.resume(Some(breakpoint))
+    }
+
+    pub fn program_counter(&self) -> i32 {
+        self.ctx.program_counter()
+    }
+
+    pub fn state(&self) -> &I::State {
+        self.ctx.state()
+    }
+
+    pub fn enable_tracing(&mut self) {
+        self.ctx.enable_tracing();
+    }
+
+    pub fn trace(&self) -> Option<&trace::Trace<I>> {
+        self.ctx.trace()
+    }
+
+    pub fn executed_instructions(&self) -> &HashSet<i32> {
+        self.ctx.executed_instructions()
+    }
+
+    pub fn current_instruction(&self) -> Option<&I::Instruction> {
+        self.program.get(self.ctx.program_counter())
+    }
+
+    pub fn exit_reason(&self) -> Option<ExitReason> {
+        self.exit
+    }
+
+    fn resume(&mut self, until: Option<i32>) -> Stop<I::State> {
+        loop {
+            if let Some(stop) = self.execute() {
+                return stop;
+            }
+            let pc = self.ctx.program_counter();
+            if until == Some(pc) || self.breakpoints.contains(&pc) {
+                return Stop::Breakpoint(pc);
+            }
+        }
+    }
+
+    fn execute(&mut self) -> Option<Stop<I::State>> {
+        if let Some(reason) = self.exit {
+            return Some(Stop::Exit(reason));
+        }
+        let old = self.ctx.state().clone();
+        self.exit = self.ctx.step(self.program);
+        if let Some(reason) = self.exit {
+            return Some(Stop::Exit(reason));
+        }
+        let new = self.ctx.state();
+        self.watches
+            .iter()
+            .find(|watch| match watch {
+                Watch::StateChanged => old != *new,
+                Watch::StateEquals(val) => old != *new && new == val,
+            })
+            .map(|watch| Stop::Watch {
+                watch: watch.clone(),
+                old,
+                new: new.clone(),
+            })
     }
 }
 
@@ -116,6 +758,18 @@ acc +1
 jmp -4
 acc +6";
 
+    static TEST_ASSEMBLY: &str = r"
+start:  nop +0      ; does nothing
+again:  acc +1
+        jmp skip
+back:   acc +3
+        jmp again
+        acc -99
+skip:
+        acc +1
+        jmp back
+        acc +6";
+
     #[test]
     fn test_run_to_first_loop() {
         let program = Program::new(TEST_INPUT);
@@ -127,9 +781,290 @@ acc +6";
     #[test]
     fn test_modify_to_terminate() {
         let program = Program::new(TEST_INPUT);
-        let program = program.modify_to_terminate();
+        let program = program.modify_to_terminate().unwrap();
         let mut ctx = ExecutionContext::new();
         ctx.run(&program);
         assert_eq!(8, ctx.accumulator());
     }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Ok(OpCode::Jmp(-4)), "jmp -4".parse::<OpCode>());
+        assert_eq!(
+            Err(Error::InstructionCount(2)),
+            "acc +1\nacc +2".parse::<OpCode>()
+        );
+        assert!(matches!(
+            "nop".parse::<Program>(),
+            Err(Error::MissingOperand { line: 1, column: 4 })
+        ));
+        assert_eq!(
+            Err(Error::NoTerminatingFix),
+            "acc +1\njmp -1\njmp -1"
+                .parse::<Program>()
+                .unwrap()
+                .modify_to_terminate()
+        );
+    }
+
+    #[test]
+    fn test_exit_reasons() {
+        let run = |s: &str| ExecutionContext::new().run(&s.parse().unwrap());
+        assert_eq!(ExitReason::Terminate, run("nop +0\njmp +2\nacc +1"));
+        assert_eq!(ExitReason::OutOfBounds(4), run("nop +0\njmp +3\nacc +1"));
+        assert_eq!(
+            ExitReason::NegativeProgramCounter(-1),
+            run("acc +1\njmp -2")
+        );
+        assert_eq!(
+            ExitReason::OutOfBounds(i32::MAX),
+            run(&format!("nop +0\njmp {}", i32::MAX))
+        );
+        assert_eq!(ExitReason::Terminate, run(""));
+
+        let mut ctx = ExecutionContext::new();
+        let program = format!("acc {}\nacc +1\nacc -1", i32::MAX).parse().unwrap();
+        assert_eq!(ExitReason::Overflow(1), ctx.run(&program));
+        assert_eq!(i32::MAX, ctx.accumulator());
+        let program = format!("acc {}\nacc -2", i32::MIN + 1).parse().unwrap();
+        assert_eq!(
+            ExitReason::Overflow(1),
+            ExecutionContext::new().run(&program)
+        );
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        assert_eq!(
+            Err(Error::InvalidOperand {
+                line: 2,
+                column: 7,
+                operand: "start+1".to_string()
+            }),
+            Program::<Handheld>::assemble("start: nop +0\n  jmp start+1")
+        );
+        assert_eq!(
+            Err(Error::UnknownOpCode {
+                line: 2,
+                column: 3,
+                opcode: "mul".to_string()
+            }),
+            Program::<Handheld>::assemble("nop +0\n  mul +2")
+        );
+        assert_eq!(
+            Err(Error::MissingOperand { line: 1, column: 7 }),
+            Program::<Handheld>::assemble("l: acc  ; comment")
+        );
+        assert_eq!(
+            Err(Error::UndefinedLabel {
+                line: 1,
+                column: 5,
+                label: "nowhere".to_string()
+            }),
+            Program::<Handheld>::assemble("jmp nowhere")
+        );
+    }
+
+    #[test]
+    fn test_assemble_disassemble() {
+        let program = Program::assemble(TEST_ASSEMBLY).unwrap();
+        let mut ctx = ExecutionContext::new();
+        assert_eq!(ExitReason::Loop, ctx.run(&program));
+        assert_eq!(5, ctx.accumulator());
+        assert_eq!(Some(3), program.label("back"));
+
+        let text = program.disassemble();
+        assert_eq!(TEST_INPUT, text.trim());
+        assert_eq!(program.codes, Program::new(&text).codes);
+    }
+
+    #[derive(Clone, Debug, Default, PartialEq)]
+    struct Registers {
+        acc: i32,
+        b: i32,
+        output: Vec<i32>,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Extended {
+        Base(OpCode),
+        Mul(i32),
+        Mov(i32),
+        Dec,
+        Jnz(i32),
+        Out,
+        Hlt,
+    }
+
+    impl std::fmt::Display for Extended {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Extended::Base(op) => write!(f, "{}", op),
+                Extended::Mul(val) => write!(f, "mul {:+}", val),
+                Extended::Mov(val) => write!(f, "mov b {:+}", val),
+                Extended::Dec => write!(f, "dec b"),
+                Extended::Jnz(val) => write!(f, "jnz b {:+}", val),
+                Extended::Out => write!(f, "out"),
+                Extended::Hlt => write!(f, "hlt"),
+            }
+        }
+    }
+
+    struct ExtendedSet;
+
+    impl InstructionSet for ExtendedSet {
+        type Instruction = Extended;
+        type State = Registers;
+
+        const LOOP_ON_REVISIT: bool = false;
+
+        fn decode(
+            mnemonic: &str,
+            operands: &[Operand],
+        ) -> std::result::Result<Extended, DecodeError> {
+            use Operand::*;
+            match (mnemonic, operands) {
+                ("mul", [Value(val)]) => Ok(Extended::Mul(*val)),
+                ("mov", [Symbol("b"), Value(val)]) => Ok(Extended::Mov(*val)),
+                ("dec", [Symbol("b")]) => Ok(Extended::Dec),
+                ("jnz", [Symbol("b"), Value(val)]) => Ok(Extended::Jnz(*val)),
+                ("out", []) => Ok(Extended::Out),
+                ("hlt", []) => Ok(Extended::Hlt),
+                ("dec", [_]) => Err(DecodeError::InvalidOperand(0)),
+                ("mul", _) | ("dec", _) => Err(DecodeError::OperandCount(1)),
+                ("mov", _) | ("jnz", _) => Err(DecodeError::OperandCount(2)),
+                ("out", _) | ("hlt", _) => Err(DecodeError::OperandCount(0)),
+                _ => Handheld::decode(mnemonic, operands).map(Extended::Base),
+            }
+        }
+
+        fn execute(instruction: &Extended, regs: &mut Registers) -> Flow {
+            match instruction {
+                Extended::Base(op) => Handheld::execute(op, &mut regs.acc),
+                Extended::Mul(val) => {
+                    regs.acc *= val;
+                    Flow::Next
+                }
+                Extended::Mov(val) => {
+                    regs.b = *val;
+                    Flow::Next
+                }
+                Extended::Dec => {
+                    regs.b -= 1;
+                    Flow::Next
+                }
+                Extended::Jnz(offset) if regs.b != 0 => Flow::Jump(*offset),
+                Extended::Jnz(_) => Flow::Next,
+                Extended::Out => {
+                    regs.output.push(regs.acc);
+                    Flow::Next
+                }
+                Extended::Hlt => Flow::Halt,
+            }
+        }
+    }
+
+    // Reports an operand count that agrees with what it was given, which the
+    // assembler can't map onto a more specific error.
+    struct BrokenSet;
+
+    impl InstructionSet for BrokenSet {
+        type Instruction = Extended;
+        type State = Registers;
+
+        fn decode(
+            _mnemonic: &str,
+            operands: &[Operand],
+        ) -> std::result::Result<Extended, DecodeError> {
+            Err(DecodeError::OperandCount(operands.len()))
+        }
+
+        fn execute(instruction: &Extended, regs: &mut Registers) -> Flow {
+            ExtendedSet::execute(instruction, regs)
+        }
+    }
+
+    #[test]
+    fn test_extended_instruction_set() {
+        let program = Program::<ExtendedSet>::assemble(
+            r"
+        acc +1
+        mov b +3
+again:  mul +2
+        dec b
+        jnz b again
+        out
+        hlt
+        acc +100",
+        )
+        .unwrap();
+        assert_eq!(Extended::Jnz(-2), program.codes[4]);
+
+        let mut ctx = ExecutionContext::<ExtendedSet>::default();
+        assert_eq!(ExitReason::Terminate, ctx.run(&program));
+        assert_eq!(vec![8], ctx.state().output);
+        assert_eq!(8, ctx.state().acc);
+
+        assert_eq!(
+            Err(Error::UndefinedLabel {
+                line: 1,
+                column: 5,
+                label: "c".to_string()
+            }),
+            Program::<ExtendedSet>::assemble("dec c")
+        );
+        assert_eq!(
+            Err(Error::InvalidInstruction {
+                line: 1,
+                column: 1,
+                opcode: "hlt".to_string(),
+                error: DecodeError::OperandCount(2),
+            }),
+            Program::<BrokenSet>::assemble("hlt b +1")
+        );
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let program = Program::<ExtendedSet>::assemble("mov b +1\nspin: jnz b spin").unwrap();
+        let mut ctx = ExecutionContext::<ExtendedSet>::default();
+        ctx.set_step_limit(100);
+        assert_eq!(ExitReason::StepLimit(100), ctx.run(&program));
+        assert_eq!(100, ctx.steps());
+        assert_eq!(1, ctx.program_counter());
+    }
+
+    #[test]
+    fn test_debugger() {
+        let program = Program::new(TEST_INPUT);
+        let mut dbg = Debugger::new(&program);
+        assert_eq!(Stop::Step, dbg.step());
+        assert_eq!(1, dbg.program_counter());
+        assert_eq!(Some(&OpCode::Acc(1)), dbg.current_instruction());
+
+        assert_eq!(Stop::Breakpoint(7), dbg.run_until(7));
+        assert_eq!(2, dbg.accumulator());
+
+        dbg.add_watch(Watch::StateEquals(5));
+        assert_eq!(
+            Stop::Watch {
+                watch: Watch::StateEquals(5),
+                old: 2,
+                new: 5
+            },
+            dbg.cont()
+        );
+        assert_eq!(4, dbg.program_counter());
+
+        dbg.add_breakpoint(4);
+        assert_eq!(Stop::Exit(ExitReason::Loop), dbg.cont());
+        assert_eq!(
+            [0, 1, 2, 3, 4, 6, 7]
+                .iter()
+                .copied()
+                .collect::<HashSet<_>>(),
+            *dbg.executed_instructions()
+        );
+        assert_eq!(Stop::Exit(ExitReason::Loop), dbg.step());
+    }
 }
@@ -0,0 +1,218 @@
+use super::Error;
+use super::OpCode;
+use super::Program;
+use std::collections::HashSet;
+
+type Result<T> = std::result::Result<T, Error>;
+
+// Control flow graph over the instructions of a program. Node `len()` is a
+// synthetic exit node reached by running into the instruction immediately
+// after the last one; jumps anywhere else outside the program have no
+// successor.
+pub struct ControlFlowGraph {
+    successors: Vec<Option<usize>>,
+    predecessors: Vec<Vec<usize>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Repair {
+    pub pc: usize,
+    pub original: OpCode,
+    pub replacement: OpCode,
+    pub path: Vec<usize>,
+    pub program: Program,
+}
+
+fn successor(pc: usize, code: &OpCode, len: usize) -> Option<usize> {
+    let next = match code {
+        OpCode::Nop(_) | OpCode::Acc(_) => pc as i64 + 1,
+        OpCode::Jmp(offset) => pc as i64 + *offset as i64,
+    };
+    if next < 0 || next > len as i64 {
+        None
+    } else {
+        Some(next as usize)
+    }
+}
+
+fn flip(code: &OpCode) -> Option<OpCode> {
+    match *code {
+        OpCode::Nop(val) => Some(OpCode::Jmp(val)),
+        OpCode::Jmp(val) => Some(OpCode::Nop(val)),
+        OpCode::Acc(_) => None,
+    }
+}
+
+impl ControlFlowGraph {
+    pub fn new(program: &Program) -> ControlFlowGraph {
+        let len = program.codes.len();
+        let successors = program
+            .codes
+            .iter()
+            .enumerate()
+            .map(|(pc, code)| successor(pc, code, len))
+            .collect::<Vec<_>>();
+        let mut predecessors = vec![Vec::new(); len + 1];
+        for (pc, next) in successors.iter().enumerate() {
+            if let Some(next) = next {
+                predecessors[*next].push(pc);
+            }
+        }
+        ControlFlowGraph {
+            successors,
+            predecessors,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.successors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.successors.is_empty()
+    }
+
+    pub fn exit(&self) -> usize {
+        self.len()
+    }
+
+    pub fn successor(&self, pc: usize) -> Option<usize> {
+        self.successors.get(pc).copied().flatten()
+    }
+
+    pub fn predecessors(&self, pc: usize) -> &[usize] {
+        &self.predecessors[pc]
+    }
+
+    // Instructions that eventually run off the end of the program, found by
+    // walking predecessor edges back from the exit node.
+    pub fn reaches_termination(&self) -> HashSet<usize> {
+        let mut reached = HashSet::new();
+        let mut frontier = vec![self.exit()];
+        while let Some(pc) = frontier.pop() {
+            if reached.insert(pc) {
+                frontier.extend(self.predecessors(pc));
+            }
+        }
+        reached
+    }
+
+    // Instructions executed from the start of the program, in order, stopping
+    // before the first repeated instruction.
+    pub fn path(&self) -> Vec<usize> {
+        let mut seen = HashSet::new();
+        let mut path = Vec::new();
+        let mut pc = Some(0);
+        while let Some(current) = pc {
+            if current >= self.len() || !seen.insert(current) {
+                break;
+            }
+            path.push(current);
+            pc = self.successor(current);
+        }
+        path
+    }
+}
+
+// Finds the single nop/jmp flip that makes the program terminate. Because
+// only one instruction changes, the repaired program terminates iff some
+// instruction on the original path flips to a successor that already reaches
+// the exit node, so every candidate is checked in constant time.
+pub fn repair(program: &Program) -> Result<Repair> {
+    let cfg = ControlFlowGraph::new(program);
+    let terminating = cfg.reaches_termination();
+    if terminating.contains(&0) || cfg.is_empty() {
+        return Err(Error::AlreadyTerminates);
+    }
+    let len = cfg.len();
+    let (pc, replacement) = cfg
+        .path()
+        .into_iter()
+        .filter_map(|pc| flip(&program.codes[pc]).map(|code| (pc, code)))
+        .find(|(pc, code)| match successor(*pc, code, len) {
+            Some(next) => terminating.contains(&next),
+            None => false,
+        })
+        .ok_or(Error::NoTerminatingFix)?;
+
+    let mut repaired = program.clone();
+    let original = std::mem::replace(&mut repaired.codes[pc], replacement);
+    let path = ControlFlowGraph::new(&repaired).path();
+    Ok(Repair {
+        pc,
+        original,
+        replacement,
+        path,
+        program: repaired,
+    })
+}
+
+impl std::fmt::Display for Repair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "replaced instruction {} ({} -> {}); terminates after {} instructions: {}",
+            self.pc,
+            self.original,
+            self.replacement,
+            self.path.len(),
+            self.path
+                .iter()
+                .map(|pc| pc.to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_console::ExecutionContext;
+    use crate::game_console::ExitReason;
+
+    static TEST_INPUT: &str = r"nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    #[test]
+    fn test_reaches_termination() {
+        let cfg = ControlFlowGraph::new(&Program::new(TEST_INPUT));
+        assert_eq!(
+            [8, 9].iter().copied().collect::<HashSet<_>>(),
+            cfg.reaches_termination()
+        );
+        assert_eq!(vec![0, 1, 2, 6, 7, 3, 4], cfg.path());
+    }
+
+    #[test]
+    fn test_repair() {
+        let repair = repair(&Program::new(TEST_INPUT)).unwrap();
+        assert_eq!(7, repair.pc);
+        assert_eq!(OpCode::Jmp(-4), repair.original);
+        assert_eq!(OpCode::Nop(-4), repair.replacement);
+        assert_eq!(vec![0, 1, 2, 6, 7, 8], repair.path);
+
+        let mut ctx = ExecutionContext::new();
+        assert_eq!(ExitReason::Terminate, ctx.run(&repair.program));
+        assert_eq!(8, ctx.accumulator());
+    }
+
+    #[test]
+    fn test_repair_errors() {
+        assert_eq!(
+            Err(Error::AlreadyTerminates),
+            repair(&Program::new("nop +0\nacc +1"))
+        );
+        assert_eq!(
+            Err(Error::NoTerminatingFix),
+            repair(&Program::new("acc +1\njmp -1\njmp -1"))
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use super::Handheld;
+use super::InstructionSet;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEntry<T, S> {
+    pub pc: i32,
+    pub instruction: T,
+    pub before: S,
+    pub after: S,
+    pub next: i32,
+}
+
+pub struct Trace<I: InstructionSet = Handheld> {
+    entries: Vec<TraceEntry<I::Instruction, I::State>>,
+}
+
+// Machine state that can be written out as a JSON value. Integers are emitted
+// as numbers; anything else should go through `json_string`.
+pub trait ToJson {
+    fn to_json(&self) -> String;
+}
+
+macro_rules! impl_to_json_for_int {
+    ($($t:ty),*) => {
+        $(impl ToJson for $t {
+            fn to_json(&self) -> String {
+                self.to_string()
+            }
+        })*
+    };
+}
+
+impl_to_json_for_int!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+impl ToJson for str {
+    fn to_json(&self) -> String {
+        json_string(self)
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> String {
+        json_string(self)
+    }
+}
+
+// Quotes a string for JSON output.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl<I: InstructionSet> Default for Trace<I> {
+    fn default() -> Self {
+        Trace {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<I: InstructionSet> Trace<I> {
+    pub(super) fn record(&mut self, entry: TraceEntry<I::Instruction, I::State>) {
+        self.entries.push(entry);
+    }
+
+    pub fn entries(&self) -> &[TraceEntry<I::Instruction, I::State>] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // The instruction the trace jumped back to at the end, if the last
+    // instruction executed led to one that had already run.
+    pub fn loop_entry(&self) -> Option<i32> {
+        self.loop_start().map(|i| self.entries[i].pc)
+    }
+
+    pub fn loop_body(&self) -> Option<Vec<i32>> {
+        self.loop_start()
+            .map(|i| self.entries[i..].iter().map(|e| e.pc).collect())
+    }
+
+    fn loop_start(&self) -> Option<usize> {
+        let next = self.entries.last()?.next;
+        self.entries.iter().rposition(|e| e.pc == next)
+    }
+
+    // Number of times control passed along each edge, keyed by (from, to).
+    pub fn edge_counts(&self) -> BTreeMap<(i32, i32), usize> {
+        let mut counts = BTreeMap::new();
+        for entry in self.entries.iter() {
+            *counts.entry((entry.pc, entry.next)).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    pub fn to_json_lines(&self) -> String
+    where
+        I::State: ToJson,
+    {
+        let mut out = String::new();
+        for (step, entry) in self.entries.iter().enumerate() {
+            writeln!(
+                out,
+                r#"{{"step":{},"pc":{},"instruction":{},"before":{},"after":{},"next":{}}}"#,
+                step,
+                entry.pc,
+                json_string(&entry.instruction.to_string()),
+                entry.before.to_json(),
+                entry.after.to_json(),
+                entry.next,
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    // Renders the executed part of the control flow graph. Edges are labelled
+    // with how often they were taken; the most frequently taken edges are
+    // drawn in red, and the edge that closes a detected loop is dashed.
+    pub fn to_dot(&self) -> String {
+        let mut instructions = BTreeMap::new();
+        for entry in self.entries.iter() {
+            instructions.entry(entry.pc).or_insert(&entry.instruction);
+        }
+        let counts = self.edge_counts();
+        let max = counts.values().copied().max().unwrap_or(0);
+        let back_edge = self.entries.last().filter(|_| self.loop_start().is_some());
+
+        let mut out = String::from("digraph trace {\n    node [shape=box];\n");
+        for (pc, instruction) in instructions.iter() {
+            writeln!(out, "    {} [label=\"{}: {}\"];", pc, pc, instruction).unwrap();
+        }
+        let exits = counts
+            .keys()
+            .map(|(_, to)| *to)
+            .filter(|to| !instructions.contains_key(to))
+            .collect::<BTreeSet<_>>();
+        for to in exits.iter() {
+            writeln!(out, "    {} [label=\"{}\", shape=oval];", to, to).unwrap();
+        }
+        for ((from, to), count) in counts.iter() {
+            let mut attrs = vec![format!("label=\"x{}\"", count)];
+            if max > 1 && *count == max {
+                attrs.push("color=red".to_string());
+                attrs.push("penwidth=3".to_string());
+            }
+            if back_edge.is_some_and(|e| e.pc == *from && e.next == *to) {
+                attrs.push("style=dashed".to_string());
+            }
+            writeln!(out, "    {} -> {} [{}];", from, to, attrs.join(", ")).unwrap();
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_console::ExecutionContext;
+    use crate::game_console::OpCode;
+    use crate::game_console::Program;
+
+    static TEST_INPUT: &str = r"nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    #[test]
+    fn test_trace_loop() {
+        let program = Program::new(TEST_INPUT);
+        let mut ctx = ExecutionContext::new();
+        ctx.enable_tracing();
+        ctx.run(&program);
+        let trace = ctx.take_trace().unwrap();
+        assert_eq!(7, trace.len());
+        assert_eq!(
+            TraceEntry {
+                pc: 3,
+                instruction: OpCode::Acc(3),
+                before: 2,
+                after: 5,
+                next: 4,
+            },
+            trace.entries()[5]
+        );
+        assert_eq!(Some(1), trace.loop_entry());
+        assert_eq!(Some(vec![1, 2, 6, 7, 3, 4]), trace.loop_body());
+    }
+
+    #[test]
+    fn test_trace_export() {
+        let program = Program::new("acc +2\njmp +2\nacc -1\nnop +0");
+        let mut ctx = ExecutionContext::new();
+        ctx.enable_tracing();
+        ctx.run(&program);
+        let trace = ctx.trace().unwrap();
+        assert_eq!(None, trace.loop_entry());
+        assert_eq!(
+            r#"{"step":0,"pc":0,"instruction":"acc +2","before":0,"after":2,"next":1}
+{"step":1,"pc":1,"instruction":"jmp +2","before":2,"after":2,"next":3}
+{"step":2,"pc":3,"instruction":"nop +0","before":2,"after":2,"next":4}
+"#,
+            trace.to_json_lines()
+        );
+        assert_eq!(
+            r#"digraph trace {
+    node [shape=box];
+    0 [label="0: acc +2"];
+    1 [label="1: jmp +2"];
+    3 [label="3: nop +0"];
+    4 [label="4", shape=oval];
+    0 -> 1 [label="x1"];
+    1 -> 3 [label="x1"];
+    3 -> 4 [label="x1"];
+}
+"#,
+            trace.to_dot()
+        );
+    }
+
+    #[test]
+    fn test_json_values() {
+        assert_eq!("-7", (-7i32).to_json());
+        assert_eq!(r#""007""#, "007".to_json());
+        assert_eq!(r#""-0""#, "-0".to_json());
+        assert_eq!(r#""a\"b\n""#, "a\"b\n".to_json());
+    }
+}
@@ -1,6 +1,11 @@
 use itertools::Itertools;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use thiserror::Error as ThisError;
 
+pub mod generate;
+pub mod render;
+
 #[derive(ThisError, Debug)]
 pub enum Error {
     #[error("failed to parse tile data")]
@@ -17,11 +22,58 @@ pub enum Error {
 
     #[error("invalid Tile ID header")]
     InvalidTileHeader,
+
+    #[error("got {tiles} tiles; want {width}x{height}")]
+    TileCount {
+        tiles: usize,
+        width: usize,
+        height: usize,
+    },
+
+    #[error("no arrangement of the tiles fits together")]
+    Unsolvable,
+
+    #[error("tiles fit together in {0} different ways")]
+    Underdetermined(usize),
+
+    #[error("tile side of {0} cells is longer than the supported 64")]
+    TileTooLarge(usize),
+
+    #[error("tile {0} is not a non-empty square")]
+    TileNotSquare(TileId),
+
+    #[error("tile {id} has side {size}; the first tile has {want}")]
+    MixedTileSizes {
+        id: TileId,
+        size: usize,
+        want: usize,
+    },
+
+    #[error("unexpected character '{0}' in picture")]
+    InvalidCell(char),
+
+    #[error("pattern has no '#' cells")]
+    EmptyPattern,
+
+    #[error("tile size {0} is outside the supported 3 to 64")]
+    InvalidTileSize(usize),
+
+    #[error("{tiles} tiles of size {size} can't all have distinct edges")]
+    TooManyTiles { tiles: usize, size: usize },
+
+    #[error("could not fit {0} non-overlapping patterns in the image")]
+    PatternDoesNotFit(usize),
+
+    #[error("image is {got:?} cells; assembly is {want:?}")]
+    ImageSize {
+        got: (usize, usize),
+        want: (usize, usize),
+    },
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
-#[derive(PartialEq, Eq, Hash, Debug)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
 enum Direction {
     Top,
     Bottom,
@@ -29,9 +81,6 @@ enum Direction {
     Right,
 }
 
-#[derive(Debug)]
-struct Border(String);
-
 #[derive(Debug, Clone)]
 struct Tile {
     id: TileId,
@@ -41,12 +90,59 @@ struct Tile {
 type TileId = usize;
 
 #[derive(Debug)]
-pub struct Tiles(Vec<Tile>);
+pub struct Tiles {
+    orientations: Vec<Tile>,
+    index: EdgeIndex,
+}
+
+// Every border of every orientation as a bitmask ('#' cells are set bits),
+// plus a lookup from each side's border to the orientations that have it.
+#[derive(Debug)]
+struct EdgeIndex {
+    borders: Vec<[u64; 4]>,
+    by_side: [HashMap<u64, Vec<usize>>; 4],
+    redundant: Vec<bool>,
+}
 
 pub struct Assembly(Vec<Vec<Tile>>);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Image(Vec<Vec<i8>>);
 
+static SEA_MONSTER: &str = "                  # \n#    ##    ##    ###\n #  #  #  #  #  #   ";
+
+// A shape to look for in an image, drawn with '#' for the cells that must be
+// set and ' ' or '.' for cells that don't matter.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    // The '#' offsets of each distinct rotation or reflection of the pattern.
+    variants: Vec<(Orientation, Vec<(usize, usize)>)>,
+}
+
+// How a pattern was transformed to match: turned clockwise by
+// `quarter_turns`, then mirrored left to right if `flipped`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Orientation {
+    pub quarter_turns: usize,
+    pub flipped: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchMode {
+    Overlapping,
+    // Matches are taken in reading order, skipping any that share a cell
+    // with an earlier match.
+    NonOverlapping,
+}
+
+// The top-left corner of the transformed pattern's bounding box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    pub row: usize,
+    pub col: usize,
+    pub orientation: Orientation,
+}
+
 impl std::str::FromStr for Tile {
     type Err = Error;
 
@@ -62,7 +158,19 @@ impl std::str::FromStr for Tile {
         let data = lines
             .map(|l| l.chars().collect::<Vec<_>>())
             .collect::<Vec<_>>();
-        Ok(Tile { id, data })
+        let side = data.iter().map(Vec::len).chain(Some(data.len())).max();
+        match side {
+            Some(n) if n > 64 => return Err(Error::TileTooLarge(n)),
+            Some(0) => return Err(Error::TileNotSquare(id)),
+            _ => {}
+        }
+        if data.iter().any(|l| l.len() != data.len()) {
+            return Err(Error::TileNotSquare(id));
+        }
+        match data.iter().flatten().find(|&&c| c != '.' && c != '#') {
+            Some(&c) => Err(Error::InvalidCell(c)),
+            None => Ok(Tile { id, data }),
+        }
     }
 }
 
@@ -78,12 +186,27 @@ impl std::str::FromStr for Tiles {
                 Ok(tile)
             })
             .collect::<Result<Vec<_>>>()?;
-        let tiles = tiles
-            .iter()
-            .map(|t| t.rotations())
-            .flatten()
-            .collect::<Vec<_>>();
-        Ok(Tiles(tiles))
+        // Edges are indexed as bitmasks without their length, so tiles of
+        // different sizes could match falsely.
+        let want = tiles[0].data.len();
+        if let Some(tile) = tiles.iter().find(|t| t.data.len() != want) {
+            return Err(Error::MixedTileSizes {
+                id: tile.id,
+                size: tile.data.len(),
+                want,
+            });
+        }
+        Ok(Tiles::new(&tiles))
+    }
+}
+
+impl std::fmt::Display for Tile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Tile {}:", self.id)?;
+        for line in self.data.iter() {
+            write!(f, "\n{}", line.iter().collect::<String>())?;
+        }
+        Ok(())
     }
 }
 
@@ -100,9 +223,9 @@ impl Direction {
 
 fn rotate<T: Default + Clone + Copy>(v: &[Vec<T>]) -> Vec<Vec<T>> {
     let (h, w) = (v.len(), v[0].len());
-    let mut rot = vec![vec![Default::default(); w]; h];
+    let mut rot = vec![vec![Default::default(); h]; w];
     for (i, j) in (0..h).cartesian_product(0..w) {
-        rot[j][w - 1 - i] = v[i][j];
+        rot[j][h - 1 - i] = v[i][j];
     }
     rot
 }
@@ -117,50 +240,69 @@ fn flip_horizontal<T: Clone>(v: &[Vec<T>]) -> Vec<Vec<T>> {
         .collect::<Vec<_>>()
 }
 
+// All eight rotations and reflections of a grid.
+fn symmetries<T: Default + Clone + Copy>(v: &[Vec<T>]) -> Vec<Vec<Vec<T>>> {
+    let mut all = vec![flip_horizontal(v), v.to_vec()];
+    for _i in 0..3 {
+        let last = all.last().unwrap();
+        let rotated = rotate(last);
+        all.push(flip_horizontal(&rotated));
+        all.push(rotated);
+    }
+    all
+}
+
+// Tiles placed so far, as indices into `Tiles`, plus which tiles are in use.
+struct Search<'a> {
+    orientations: &'a [Tile],
+    index: &'a EdgeIndex,
+    width: usize,
+    height: usize,
+    placed: Vec<usize>,
+    used: Vec<bool>,
+    // Scan every orientation instead of using the index, optionally comparing
+    // borders as strings the way the original solver did; only useful for
+    // benchmarking the index.
+    linear_scan: bool,
+    string_borders: bool,
+}
+
+const SIDES: [Direction; 4] = [
+    Direction::Top,
+    Direction::Bottom,
+    Direction::Left,
+    Direction::Right,
+];
+
 impl Tile {
-    fn matches(&self, other: &Tile, side: &Direction) -> bool {
-        self.border(side) == other.border(&side.opposite())
+    fn border(&self, dir: &Direction) -> u64 {
+        let cells = match dir {
+            Direction::Top => self.data.first().unwrap().clone(),
+            Direction::Bottom => self.data.last().unwrap().clone(),
+            Direction::Left => self.data.iter().map(|l| *l.first().unwrap()).collect(),
+            Direction::Right => self.data.iter().map(|l| *l.last().unwrap()).collect(),
+        };
+        cells
+            .iter()
+            .enumerate()
+            .filter(|(_, &c)| c == '#')
+            .fold(0, |bits, (i, _)| bits | 1 << i)
     }
 
-    fn border(&self, dir: &Direction) -> String {
+    fn border_string(&self, dir: &Direction) -> String {
         match dir {
-            Direction::Top => self.data.first().unwrap().iter().collect::<String>(),
-            Direction::Bottom => self.data.last().unwrap().iter().collect::<String>(),
-            Direction::Left => self
-                .data
-                .iter()
-                .map(|l| l.first().unwrap())
-                .collect::<String>(),
-            Direction::Right => self
-                .data
-                .iter()
-                .map(|l| l.last().unwrap())
-                .collect::<String>(),
+            Direction::Top => self.data.first().unwrap().iter().collect(),
+            Direction::Bottom => self.data.last().unwrap().iter().collect(),
+            Direction::Left => self.data.iter().map(|l| l.first().unwrap()).collect(),
+            Direction::Right => self.data.iter().map(|l| l.last().unwrap()).collect(),
         }
     }
 
     fn rotations(&self) -> Vec<Tile> {
-        let mut tiles = vec![
-            Tile {
-                id: self.id,
-                data: flip_horizontal(&self.data),
-            },
-            self.clone(),
-        ];
-        for _i in 0..3 {
-            let last = tiles.last().unwrap();
-            let new_tile_rotated = Tile {
-                id: last.id,
-                data: flip_horizontal(&rotate(&last.data)),
-            };
-            let new_tile = Tile {
-                id: last.id,
-                data: rotate(&last.data),
-            };
-            tiles.push(new_tile_rotated);
-            tiles.push(new_tile);
-        }
-        tiles
+        symmetries(&self.data)
+            .into_iter()
+            .map(|data| Tile { id: self.id, data })
+            .collect()
     }
 
     fn data(&self) -> Vec<Vec<i8>> {
@@ -187,48 +329,240 @@ impl Tile {
     }
 }
 
-impl Tiles {
-    fn find_top_left(&self) -> &Tile {
-        self.0
+impl EdgeIndex {
+    fn new(orientations: &[Tile]) -> EdgeIndex {
+        let borders = orientations
             .iter()
-            .find(|t| {
-                self.find_tile(t, &Direction::Top).is_none()
-                    && self.find_tile(t, &Direction::Left).is_none()
+            .map(|t| {
+                let mut borders = [0; 4];
+                for (border, side) in borders.iter_mut().zip(SIDES.iter()) {
+                    *border = t.border(side);
+                }
+                borders
             })
-            .unwrap()
+            .collect::<Vec<_>>();
+        let mut by_side: [HashMap<u64, Vec<usize>>; 4] = Default::default();
+        for (i, b) in borders.iter().enumerate() {
+            for (side, map) in by_side.iter_mut().enumerate() {
+                map.entry(b[side]).or_default().push(i);
+            }
+        }
+        // Orientations identical to an earlier orientation of the same tile
+        // would only produce duplicate solutions.
+        let redundant = orientations
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (i - i % 8..i).any(|j| orientations[j].data == t.data))
+            .collect();
+        EdgeIndex {
+            borders,
+            by_side,
+            redundant,
+        }
     }
 
-    pub fn assemble(&self, n: usize) -> Assembly {
-        let mut line_travel = self.find_top_left();
-        let mut lines = Vec::new();
-        let mut line = Vec::new();
-        let mut travel = line_travel;
-        line.push(travel.clone());
-        for _j in 1..n {
-            travel = self.find_tile(travel, &Direction::Right).unwrap();
-            line.push(travel.clone());
+    fn len(&self) -> usize {
+        self.borders.len()
+    }
+
+    // Orientations whose border on `side` is `border`.
+    fn with_border(&self, side: Direction, border: u64) -> &[usize] {
+        self.by_side[side as usize]
+            .get(&border)
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+impl Tiles {
+    fn new(tiles: &[Tile]) -> Tiles {
+        let orientations = tiles.iter().flat_map(|t| t.rotations()).collect::<Vec<_>>();
+        let index = EdgeIndex::new(&orientations);
+        Tiles {
+            orientations,
+            index,
         }
-        lines.push(line);
-        for _i in 1..n {
-            line_travel = self.find_tile(line_travel, &Direction::Bottom).unwrap();
-            let mut line = Vec::new();
-            let mut travel = line_travel;
-            line.push(travel.clone());
-            for _j in 1..n {
-                travel = self.find_tile(travel, &Direction::Right).unwrap();
-                line.push(travel.clone());
+    }
+
+    // Number of distinct tiles, each of which is stored in all eight
+    // orientations.
+    pub fn len(&self) -> usize {
+        self.orientations.len() / 8
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orientations.is_empty()
+    }
+
+    // Finds any arrangement of the tiles into a `width` by `height` grid.
+    pub fn assemble(&self, width: usize, height: usize) -> Result<Assembly> {
+        let mut found = None;
+        self.search(width, height)?.run(&mut |placed| {
+            found = Some(placed.to_vec());
+            false
+        });
+        found
+            .map(|placed| self.assembly(width, &placed))
+            .ok_or(Error::Unsolvable)
+    }
+
+    // Like `assemble`, but fails unless the arrangement is unique up to
+    // rotating or flipping the whole puzzle.
+    pub fn solve(&self, width: usize, height: usize) -> Result<Assembly> {
+        let mut solutions = self.solutions(width, height)?;
+        match solutions.len() {
+            0 => Err(Error::Unsolvable),
+            1 => Ok(solutions.remove(0)),
+            n => Err(Error::Underdetermined(n)),
+        }
+    }
+
+    // Every distinct arrangement of the tiles; solutions that are rotations or
+    // reflections of each other are only reported once.
+    pub fn solutions(&self, width: usize, height: usize) -> Result<Vec<Assembly>> {
+        let mut seen = HashSet::new();
+        let mut solutions = Vec::new();
+        self.search(width, height)?.run(&mut |placed| {
+            let assembly = self.assembly(width, placed);
+            if seen.insert(assembly.canonical_key()) {
+                solutions.push(assembly);
             }
-            lines.push(line);
+            true
+        });
+        Ok(solutions)
+    }
+
+    fn search(&self, width: usize, height: usize) -> Result<Search<'_>> {
+        if width * height != self.len() || self.is_empty() {
+            return Err(Error::TileCount {
+                tiles: self.len(),
+                width,
+                height,
+            });
         }
-        Assembly(lines)
+        Ok(Search {
+            orientations: &self.orientations,
+            index: &self.index,
+            width,
+            height,
+            placed: Vec::new(),
+            used: vec![false; self.len()],
+            linear_scan: false,
+            string_borders: false,
+        })
     }
 
-    fn find_tile(&self, tile: &Tile, side: &Direction) -> Option<&Tile> {
-        let found = self
-            .0
+    fn assembly(&self, width: usize, placed: &[usize]) -> Assembly {
+        Assembly(
+            placed
+                .chunks(width)
+                .map(|row| row.iter().map(|&i| self.orientations[i].clone()).collect())
+                .collect(),
+        )
+    }
+}
+
+impl Search<'_> {
+    // Calls `on_solution` with each complete arrangement until it returns
+    // false. Returns whether the search ran to completion.
+    fn run(&mut self, on_solution: &mut dyn FnMut(&[usize]) -> bool) -> bool {
+        // One frame of untried candidates per position, kept on the heap so
+        // that large puzzles don't overflow the stack.
+        let mut frames = vec![self.untried(0)];
+        while let Some(frame) = frames.last_mut() {
+            let candidate = match frame.pop() {
+                Some(candidate) => candidate,
+                None => {
+                    frames.pop();
+                    if let Some(i) = self.placed.pop() {
+                        self.used[i / 8] = false;
+                    }
+                    continue;
+                }
+            };
+            self.used[candidate / 8] = true;
+            self.placed.push(candidate);
+            if self.placed.len() < self.width * self.height {
+                frames.push(self.untried(self.placed.len()));
+                continue;
+            }
+            let keep_going = on_solution(&self.placed);
+            self.placed.pop();
+            self.used[candidate / 8] = false;
+            if !keep_going {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Candidates for `pos` in reverse order, ready to be popped.
+    fn untried(&self, pos: usize) -> Vec<usize> {
+        let mut candidates = self.candidates(pos);
+        candidates.reverse();
+        candidates
+    }
+
+    fn border(&self, i: usize, side: Direction) -> u64 {
+        self.index.borders[i][side as usize]
+    }
+
+    // Whether orientation `i` can sit with its `side` against orientation `j`.
+    fn fits(&self, i: usize, side: Direction, j: usize) -> bool {
+        if self.string_borders {
+            self.orientations[i].border_string(&side)
+                == self.orientations[j].border_string(&side.opposite())
+        } else {
+            self.border(i, side) == self.border(j, side.opposite())
+        }
+    }
+
+    // Orientations that fit with their `side` against orientation `j`, or all
+    // of them if there is no constraint.
+    fn matching(&self, constraint: Option<(Direction, usize)>) -> Vec<usize> {
+        match constraint {
+            Some((side, j)) if !self.linear_scan => self
+                .index
+                .with_border(side, self.border(j, side.opposite()))
+                .to_vec(),
+            Some((side, j)) => (0..self.index.len())
+                .filter(|&i| self.fits(i, side, j))
+                .collect(),
+            None => (0..self.index.len()).collect(),
+        }
+    }
+
+    // Whether some other tile could sit on the given side of tile `i`.
+    fn has_neighbour(&self, i: usize, side: Direction) -> bool {
+        self.matching(Some((side.opposite(), i)))
             .iter()
-            .find(|other| other.id != tile.id && tile.matches(other, side));
-        found
+            .any(|&j| j / 8 != i / 8)
+    }
+
+    fn candidates(&self, pos: usize) -> Vec<usize> {
+        let left = match pos % self.width {
+            0 => None,
+            _ => Some(self.placed[pos - 1]),
+        };
+        let top = pos.checked_sub(self.width).map(|i| self.placed[i]);
+        let constraint = left
+            .map(|j| (Direction::Left, j))
+            .or_else(|| top.map(|j| (Direction::Top, j)));
+        let mut candidates = self
+            .matching(constraint)
+            .into_iter()
+            .filter(|&i| !self.used[i / 8] && !self.index.redundant[i])
+            .filter(|&i| left.map_or(true, |j| self.fits(i, Direction::Left, j)))
+            .filter(|&i| top.map_or(true, |j| self.fits(i, Direction::Top, j)))
+            .collect::<Vec<_>>();
+        if pos == 0 {
+            // Tiles with no neighbour above or to the left are the likeliest
+            // top-left corners, so try them first.
+            candidates.sort_by_key(|&i| {
+                self.has_neighbour(i, Direction::Top) as u8
+                    + self.has_neighbour(i, Direction::Left) as u8
+            });
+        }
+        candidates
     }
 }
 
@@ -250,6 +584,49 @@ impl std::fmt::Display for Assembly {
 }
 
 impl Assembly {
+    pub fn width(&self) -> usize {
+        self.0[0].len()
+    }
+
+    pub fn height(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn ids(&self) -> Vec<Vec<TileId>> {
+        self.0
+            .iter()
+            .map(|row| row.iter().map(|t| t.id).collect())
+            .collect()
+    }
+
+    // The full picture including tile borders.
+    fn picture(&self) -> Vec<Vec<char>> {
+        self.0
+            .iter()
+            .flat_map(|row| {
+                (0..row[0].data.len()).map(move |i| {
+                    row.iter()
+                        .flat_map(|t| t.data[i].iter().copied())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect()
+    }
+
+    // Identifies a solution regardless of how the whole puzzle is rotated or
+    // flipped: the smallest of its symmetric variants that keep the same
+    // dimensions.
+    fn canonical_key(&self) -> (Vec<Vec<TileId>>, Vec<Vec<char>>) {
+        let ids = self.ids();
+        let picture = self.picture();
+        symmetries(&ids)
+            .into_iter()
+            .zip(symmetries(&picture))
+            .filter(|(i, _)| i.len() == ids.len())
+            .min()
+            .unwrap()
+    }
+
     pub fn corner_ids(&self) -> Vec<TileId> {
         vec![
             self.0.first().unwrap().first().unwrap().id,
@@ -259,7 +636,8 @@ impl Assembly {
         ]
     }
 
-    pub fn images(&self) -> Vec<Image> {
+    // The picture with tile borders removed, in the assembly's orientation.
+    pub fn image(&self) -> Image {
         // For each vert group
         let mut data = Vec::new();
         for vert_group in self.0.iter() {
@@ -269,15 +647,11 @@ impl Assembly {
             }
             data.append(&mut line);
         }
-        let mut images = vec![Image(flip_horizontal(&data)), Image(data)];
-        for _i in 0..3 {
-            let last = images.last().unwrap();
-            let new_image_rotated = Image(flip_horizontal(&rotate(&last.0)));
-            let new_image = Image(rotate(&last.0));
-            images.push(new_image_rotated);
-            images.push(new_image);
-        }
-        images
+        Image(data)
+    }
+
+    pub fn images(&self) -> Vec<Image> {
+        symmetries(&self.image().0).into_iter().map(Image).collect()
     }
 }
 
@@ -300,48 +674,152 @@ impl std::fmt::Display for Image {
     }
 }
 
-impl Image {
-    pub fn mark_sea_monsters(&mut self) -> usize {
-        static SEA_MONSTER: &[&str] = &[
-            "                  # ",
-            "#    ##    ##    ###",
-            " #  #  #  #  #  #   ",
-        ];
-        let mut sea_monster_count = 0;
-        for line in 0..self.0.len() - 3 {
-            for offset in 0..self.0[line].len() - SEA_MONSTER[0].len() {
-                if self.sea_monster_line_matches(line, offset, SEA_MONSTER[0])
-                    && self.sea_monster_line_matches(line + 1, offset, SEA_MONSTER[1])
-                    && self.sea_monster_line_matches(line + 2, offset, SEA_MONSTER[2])
-                {
-                    sea_monster_count += 1;
-                    self.mark_monster_pieces(line, offset, SEA_MONSTER[0]);
-                    self.mark_monster_pieces(line + 1, offset, SEA_MONSTER[1]);
-                    self.mark_monster_pieces(line + 2, offset, SEA_MONSTER[2]);
-                }
-            }
-        }
-        sea_monster_count
+impl std::str::FromStr for Image {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let data = s
+            .trim()
+            .lines()
+            .map(|l| {
+                l.chars()
+                    .map(|c| match c {
+                        '.' => Ok(-1),
+                        '#' => Ok(0),
+                        c => Err(Error::InvalidCell(c)),
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Image(data))
     }
+}
 
-    fn sea_monster_line_matches(&self, line: usize, col: usize, sea_monster_line: &str) -> bool {
-        !self
-            .0
-            .get(line)
-            .unwrap()
+impl std::str::FromStr for Pattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let lines = s.trim_matches('\n').lines().collect::<Vec<_>>();
+        let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        let cells = lines
             .iter()
-            .skip(col)
-            .take(sea_monster_line.len())
+            .map(|l| {
+                let mut row = l
+                    .chars()
+                    .map(|c| match c {
+                        '#' => Ok(true),
+                        ' ' | '.' => Ok(false),
+                        c => Err(Error::InvalidCell(c)),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                row.resize(width, false);
+                Ok(row)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if !cells.iter().flatten().any(|&c| c) {
+            return Err(Error::EmptyPattern);
+        }
+        let mut seen = HashSet::new();
+        let variants = symmetries(&cells)
+            .into_iter()
             .enumerate()
-            .any(|(i, &v)| sea_monster_line.chars().nth(i).unwrap() == '#' && v == -1)
+            .filter(|(_, variant)| seen.insert(variant.clone()))
+            .map(|(i, variant)| {
+                let offsets = (0..variant.len())
+                    .cartesian_product(0..variant[0].len())
+                    .filter(|&(r, c)| variant[r][c])
+                    .collect();
+                (Orientation::from_symmetry(i), offsets)
+            })
+            .collect();
+        Ok(Pattern { variants })
     }
+}
 
-    fn mark_monster_pieces(&mut self, line: usize, col: usize, sea_monster_line: &str) {
-        for i in 0..sea_monster_line.len() {
-            if sea_monster_line.chars().nth(i).unwrap() == '#' {
-                self.0[line][col + i] += 1;
+impl Orientation {
+    // The orientation of the `i`th grid returned by `symmetries`.
+    fn from_symmetry(i: usize) -> Orientation {
+        Orientation {
+            quarter_turns: i / 2,
+            flipped: i % 2 == 0,
+        }
+    }
+}
+
+impl Pattern {
+    pub fn sea_monster() -> Pattern {
+        SEA_MONSTER.parse().unwrap()
+    }
+
+    // Number of distinct orientations; fewer than 8 for symmetric patterns.
+    pub fn orientations(&self) -> usize {
+        self.variants.len()
+    }
+
+    fn offsets(&self, orientation: Orientation) -> &[(usize, usize)] {
+        self.variants
+            .iter()
+            .find(|(o, _)| *o == orientation)
+            .map_or(&[], |(_, offsets)| offsets.as_slice())
+    }
+}
+
+impl Image {
+    pub fn height(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn width(&self) -> usize {
+        self.0.first().map_or(0, Vec::len)
+    }
+
+    // Finds `pattern` in any rotation or reflection.
+    pub fn find(&self, pattern: &Pattern, mode: MatchMode) -> Vec<Match> {
+        let mut matches = Vec::new();
+        let mut taken = HashSet::new();
+        for (row, col) in (0..self.height()).cartesian_product(0..self.width()) {
+            for (orientation, offsets) in pattern.variants.iter() {
+                let cells = offsets.iter().map(|(r, c)| (row + r, col + c));
+                if !cells.clone().all(|(r, c)| {
+                    self.0
+                        .get(r)
+                        .and_then(|line| line.get(c))
+                        .is_some_and(|&v| v != -1)
+                }) {
+                    continue;
+                }
+                if mode == MatchMode::NonOverlapping {
+                    if cells.clone().any(|cell| taken.contains(&cell)) {
+                        continue;
+                    }
+                    taken.extend(cells);
+                }
+                matches.push(Match {
+                    row,
+                    col,
+                    orientation: *orientation,
+                });
+            }
+        }
+        matches
+    }
+
+    // Like `find`, but also counts each match against the cells it covers so
+    // that they no longer contribute to `roughness`. Counts stop at `i8::MAX`.
+    pub fn mark(&mut self, pattern: &Pattern, mode: MatchMode) -> Vec<Match> {
+        let matches = self.find(pattern, mode);
+        for m in matches.iter() {
+            for (r, c) in pattern.offsets(m.orientation) {
+                let cell = &mut self.0[m.row + r][m.col + c];
+                *cell = cell.saturating_add(1);
             }
         }
+        matches
+    }
+
+    pub fn mark_sea_monsters(&mut self) -> usize {
+        self.mark(&Pattern::sea_monster(), MatchMode::Overlapping)
+            .len()
     }
 
     pub fn roughness(&self) -> usize {
@@ -478,20 +956,20 @@ Tile 3079:
     #[test]
     fn test_tiles_parse() {
         let tiles = TEST_INPUT.trim().parse::<Tiles>().unwrap();
-        assert_eq!(9 * 8, tiles.0.len());
+        assert_eq!(9 * 8, tiles.orientations.len());
     }
 
     #[test]
     fn test_tiles_assemble() {
         let tiles = TEST_INPUT.trim().parse::<Tiles>().unwrap();
-        let assembly = tiles.assemble(3);
+        let assembly = tiles.assemble(3, 3).unwrap();
         assert_eq!(3, assembly.0.len());
     }
 
     #[test]
     fn test_tiles_find_corner() {
         let tiles = TEST_INPUT.trim().parse::<Tiles>().unwrap();
-        let assembly = tiles.assemble(3);
+        let assembly = tiles.assemble(3, 3).unwrap();
         println!("assembly:\n{}", assembly);
         let corners = assembly.corner_ids();
         println!("corners: {:?}", corners);
@@ -504,7 +982,7 @@ Tile 3079:
     #[test]
     fn test_find_sea_monsters() {
         let tiles = TEST_INPUT.trim().parse::<Tiles>().unwrap();
-        let mut images = tiles.assemble(3).images();
+        let mut images = tiles.assemble(3, 3).unwrap().images();
         let sea_monsters = images
             .iter_mut()
             .filter_map(|i| match i.mark_sea_monsters() {
@@ -519,7 +997,7 @@ Tile 3079:
     #[test]
     fn test_roughness() {
         let tiles = TEST_INPUT.trim().parse::<Tiles>().unwrap();
-        let mut images = tiles.assemble(3).images();
+        let mut images = tiles.assemble(3, 3).unwrap().images();
         let image = images
             .iter_mut()
             .filter_map(|i| match i.mark_sea_monsters() {
@@ -530,4 +1008,205 @@ Tile 3079:
             .unwrap();
         assert_eq!(273, image.roughness());
     }
+
+    #[test]
+    fn test_pattern_parse() {
+        assert_eq!(8, Pattern::sea_monster().orientations());
+        assert_eq!(2, "##".parse::<Pattern>().unwrap().orientations());
+        assert_eq!(1, "\n#\n".parse::<Pattern>().unwrap().orientations());
+        assert!(matches!(
+            "#x".parse::<Pattern>(),
+            Err(Error::InvalidCell('x'))
+        ));
+        assert!(matches!(
+            " .\n..".parse::<Pattern>(),
+            Err(Error::EmptyPattern)
+        ));
+    }
+
+    #[test]
+    fn test_find_any_orientation() {
+        let tiles = TEST_INPUT.trim().parse::<Tiles>().unwrap();
+        let monster = Pattern::sea_monster();
+        for image in tiles.assemble(3, 3).unwrap().images() {
+            let matches = image.find(&monster, MatchMode::Overlapping);
+            assert_eq!(2, matches.len());
+            assert_eq!(matches[0].orientation, matches[1].orientation);
+        }
+        let mut image = tiles.solve(3, 3).unwrap().image();
+        assert_eq!(2, image.mark_sea_monsters());
+        assert_eq!(273, image.roughness());
+    }
+
+    #[test]
+    fn test_match_modes() {
+        let image = "###\n#..\n#..".parse::<Image>().unwrap();
+        let pattern = "##".parse::<Pattern>().unwrap();
+        let positions = |mode| {
+            image
+                .find(&pattern, mode)
+                .iter()
+                .map(|m| (m.row, m.col, m.orientation.quarter_turns))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![(0, 0, 0), (0, 0, 1), (0, 1, 0), (1, 0, 1)],
+            positions(MatchMode::Overlapping)
+        );
+        assert_eq!(
+            vec![(0, 0, 0), (1, 0, 1)],
+            positions(MatchMode::NonOverlapping)
+        );
+
+        // The middle of the image is covered by far more than 127 matches.
+        let mut image = vec!["#".repeat(30); 30]
+            .join("\n")
+            .parse::<Image>()
+            .unwrap();
+        let pattern = vec!["#".repeat(12); 12]
+            .join("\n")
+            .parse::<Pattern>()
+            .unwrap();
+        assert_eq!(19 * 19, image.mark(&pattern, MatchMode::Overlapping).len());
+        assert_eq!(0, image.roughness());
+        assert!(image.0.iter().flatten().all(|&v| v > 0));
+    }
+
+    fn subset(ids: &[TileId]) -> Tiles {
+        TEST_INPUT
+            .split("\n\n")
+            .filter(|t| ids.iter().any(|id| t.starts_with(&format!("Tile {}:", id))))
+            .join("\n\n")
+            .parse::<Tiles>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_solve_unique() {
+        let tiles = TEST_INPUT.trim().parse::<Tiles>().unwrap();
+        let assembly = tiles.solve(3, 3).unwrap();
+        let mut corners = assembly.corner_ids();
+        corners.sort_unstable();
+        assert_eq!(vec![1171, 1951, 2971, 3079], corners);
+        assert_eq!(1427, assembly.ids()[1][1]);
+    }
+
+    #[test]
+    fn test_solve_rectangular() {
+        let tiles = subset(&[1951, 2311, 3079, 2729, 1427, 2473]);
+        for (width, height) in [(3, 2), (2, 3)].iter() {
+            let assembly = tiles.solve(*width, *height).unwrap();
+            assert_eq!((*width, *height), (assembly.width(), assembly.height()));
+            let mut corners = assembly.corner_ids();
+            corners.sort_unstable();
+            assert_eq!(vec![1951, 2473, 2729, 3079], corners);
+        }
+        assert!(matches!(tiles.solve(6, 1), Err(Error::Unsolvable)));
+    }
+
+    #[test]
+    fn test_solve_errors() {
+        let tiles = TEST_INPUT.trim().parse::<Tiles>().unwrap();
+        assert!(matches!(
+            tiles.solve(2, 2),
+            Err(Error::TileCount {
+                tiles: 9,
+                width: 2,
+                height: 2
+            })
+        ));
+        assert!(matches!(
+            subset(&[2311, 1171]).solve(2, 1),
+            Err(Error::Unsolvable)
+        ));
+
+        let blank = (1..=4)
+            .map(|id| format!("Tile {}:\n...\n...\n...", id))
+            .join("\n\n")
+            .parse::<Tiles>()
+            .unwrap();
+        assert_eq!(3, blank.solutions(2, 2).unwrap().len());
+        assert!(matches!(blank.solve(2, 2), Err(Error::Underdetermined(3))));
+        assert!(blank.assemble(2, 2).is_ok());
+    }
+
+    #[test]
+    fn test_assemble_synthetic() {
+        let puzzle = generate::Generator::new(7, 4)
+            .tile_size(12)
+            .seed(42)
+            .noise()
+            .unwrap();
+        let tiles = puzzle.to_string().parse::<Tiles>().unwrap();
+        assert_eq!(28, tiles.len());
+        assert!(puzzle.is_solution(&tiles.solve(7, 4).unwrap()));
+        assert!(matches!(
+            format!("Tile 1:\n{}", ".".repeat(65)).parse::<Tiles>(),
+            Err(Error::TileTooLarge(65))
+        ));
+        for (tile, id) in ["Tile 1:", "Tile 2:\n##\n#", "Tile 3:\n##"].iter().zip(1..) {
+            assert!(matches!(
+                tile.parse::<Tiles>(),
+                Err(Error::TileNotSquare(n)) if n == id
+            ));
+        }
+        assert!(matches!(
+            "Tile 1:\n#.\n.x".parse::<Tiles>(),
+            Err(Error::InvalidCell('x'))
+        ));
+        assert!(matches!(
+            "Tile 1:\n#..\n...\n...\n\nTile 2:\n#....\n.....\n.....\n.....\n.....".parse::<Tiles>(),
+            Err(Error::MixedTileSizes {
+                id: 2,
+                size: 5,
+                want: 3
+            })
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "expensive_tests"), ignore)]
+    fn bench_edge_index() {
+        let puzzle = generate::Generator::new(12, 12)
+            .tile_size(20)
+            .seed(7)
+            .noise()
+            .unwrap();
+        let tiles = puzzle.tiles();
+        let mut results = Vec::new();
+        // The string comparison scans every orientation and builds border
+        // strings on each comparison, as the solver did before the index.
+        for &(linear_scan, string_borders) in [(false, false), (true, false), (true, true)].iter() {
+            let start = std::time::Instant::now();
+            let mut search = tiles.search(12, 12).unwrap();
+            search.linear_scan = linear_scan;
+            search.string_borders = string_borders;
+            let mut found = None;
+            search.run(&mut |placed| {
+                found = Some(placed.to_vec());
+                false
+            });
+            println!(
+                "144 tiles, linear_scan={} string_borders={}: {:?}",
+                linear_scan,
+                string_borders,
+                start.elapsed()
+            );
+            results.push(found.unwrap());
+        }
+        assert_eq!(results[0], results[1]);
+        assert_eq!(results[0], results[2]);
+        assert!(puzzle.is_solution(&tiles.assembly(12, &results[0])));
+
+        let puzzle = generate::Generator::new(100, 100)
+            .tile_size(20)
+            .seed(11)
+            .noise()
+            .unwrap();
+        let start = std::time::Instant::now();
+        let tiles = puzzle.to_string().parse::<Tiles>().unwrap();
+        let assembly = tiles.assemble(100, 100).unwrap();
+        println!("10000 tiles, indexed: {:?}", start.elapsed());
+        assert!(puzzle.is_solution(&assembly));
+    }
 }
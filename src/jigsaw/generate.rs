@@ -0,0 +1,416 @@
+use super::symmetries;
+use super::Assembly;
+use super::Error;
+use super::Image;
+use super::Match;
+use super::Orientation;
+use super::Pattern;
+use super::Result;
+use super::Tile;
+use super::TileId;
+use super::Tiles;
+use itertools::Itertools;
+use std::collections::HashSet;
+
+// Builds puzzles in the `Tile NNNN:` format from an image or random noise.
+// Every tile edge is distinct, so each puzzle has exactly one solution up to
+// rotating or flipping the whole thing.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    width: usize,
+    height: usize,
+    tile_size: usize,
+    density: f64,
+    monsters: usize,
+    seed: u64,
+}
+
+pub struct Puzzle {
+    // Shuffled, each in a random orientation.
+    tiles: Vec<Tile>,
+    solution: Vec<Vec<TileId>>,
+    image: Image,
+    monsters: Vec<Match>,
+}
+
+// xorshift64; good enough for shuffling tiles and drawing noise.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // Zero is a fixed point of xorshift.
+        Rng(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, p: f64) -> bool {
+        ((self.next() >> 11) as f64) < p * (1u64 << 53) as f64
+    }
+}
+
+impl Generator {
+    // A `width` by `height` grid of 10 cell tiles, like the puzzle input.
+    pub fn new(width: usize, height: usize) -> Generator {
+        Generator {
+            width,
+            height,
+            tile_size: 10,
+            density: 0.3,
+            monsters: 0,
+            seed: 1,
+        }
+    }
+
+    // Side length of each tile including its border.
+    pub fn tile_size(mut self, tile_size: usize) -> Self {
+        self.tile_size = tile_size;
+        self
+    }
+
+    // Fraction of noise cells that are '#'. Keeping this low makes it
+    // unlikely that noise forms extra sea monsters by chance.
+    pub fn density(mut self, density: f64) -> Self {
+        self.density = density;
+        self
+    }
+
+    // Number of sea monsters to draw into the image, in random positions
+    // and orientations.
+    pub fn monsters(mut self, monsters: usize) -> Self {
+        self.monsters = monsters;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // Size in cells of the image once tile borders are removed.
+    pub fn image_size(&self) -> (usize, usize) {
+        let inner = self.tile_size.saturating_sub(2);
+        (self.height * inner, self.width * inner)
+    }
+
+    pub fn noise(&self) -> Result<Puzzle> {
+        let mut rng = Rng::new(self.seed);
+        let (height, width) = self.image_size();
+        let cells = (0..height)
+            .map(|_| {
+                (0..width)
+                    .map(|_| if rng.chance(self.density) { 0 } else { -1 })
+                    .collect()
+            })
+            .collect();
+        self.cut(Image(cells), &mut rng)
+    }
+
+    pub fn from_image(&self, image: &Image) -> Result<Puzzle> {
+        let got = (image.height(), image.width());
+        if got != self.image_size() {
+            return Err(Error::ImageSize {
+                got,
+                want: self.image_size(),
+            });
+        }
+        self.cut(image.clone(), &mut Rng::new(self.seed))
+    }
+
+    fn cut(&self, mut image: Image, rng: &mut Rng) -> Result<Puzzle> {
+        let size = self.tile_size;
+        if !(3..=64).contains(&size) {
+            return Err(Error::InvalidTileSize(size));
+        }
+        let (width, height) = (self.width, self.height);
+        let count = width * height;
+        if count == 0 {
+            return Err(Error::TileCount {
+                tiles: 0,
+                width,
+                height,
+            });
+        }
+        let monsters = embed(&mut image, &Pattern::sea_monster(), self.monsters, rng)?;
+
+        // Edges run between picture corners, which are shared by up to four
+        // tiles, so pick those first.
+        let corners = (0..=height)
+            .map(|_| (0..=width).map(|_| rng.next() & 1).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let mut edges = Edges::new(size);
+        let mut horizontal = vec![vec![0; width]; height + 1];
+        for (r, c) in (0..=height).cartesian_product(0..width) {
+            horizontal[r][c] = edges
+                .next(corners[r][c], corners[r][c + 1], rng)
+                .ok_or(Error::TooManyTiles { tiles: count, size })?;
+        }
+        let mut vertical = vec![vec![0; width + 1]; height];
+        for (r, c) in (0..height).cartesian_product(0..=width) {
+            vertical[r][c] = edges
+                .next(corners[r][c], corners[r + 1][c], rng)
+                .ok_or(Error::TooManyTiles { tiles: count, size })?;
+        }
+
+        let mut ids = HashSet::new();
+        let mut solution = vec![vec![0; width]; height];
+        for id in solution.iter_mut().flatten() {
+            *id = loop {
+                let id = 1000 + rng.below(9000.max(2 * count));
+                if ids.insert(id) {
+                    break id;
+                }
+            };
+        }
+
+        let inner = size - 2;
+        let bit = |bits: u64, i: usize| if bits >> i & 1 == 1 { '#' } else { '.' };
+        let mut tiles = (0..height)
+            .cartesian_product(0..width)
+            .map(|(r, c)| {
+                let mut data = vec![vec!['.'; size]; size];
+                for (i, j) in (0..inner).cartesian_product(0..inner) {
+                    if image.0[r * inner + i][c * inner + j] != -1 {
+                        data[i + 1][j + 1] = '#';
+                    }
+                }
+                for (i, line) in data.iter_mut().enumerate() {
+                    line[0] = bit(vertical[r][c], i);
+                    line[size - 1] = bit(vertical[r][c + 1], i);
+                }
+                data[0] = (0..size).map(|i| bit(horizontal[r][c], i)).collect();
+                data[size - 1] = (0..size).map(|i| bit(horizontal[r + 1][c], i)).collect();
+                Tile {
+                    id: solution[r][c],
+                    data: symmetries(&data).swap_remove(rng.below(8)),
+                }
+            })
+            .collect::<Vec<_>>();
+        for i in (1..tiles.len()).rev() {
+            tiles.swap(i, rng.below(i + 1));
+        }
+        Ok(Puzzle {
+            tiles,
+            solution,
+            image,
+            monsters,
+        })
+    }
+}
+
+// Hands out edge patterns that differ from every other edge, forwards or
+// backwards, so that each edge matches exactly one neighbour.
+struct Edges {
+    size: usize,
+    seen: HashSet<u64>,
+}
+
+impl Edges {
+    // Give up on an edge after this many collisions, since the remaining
+    // patterns are too scarce.
+    const ATTEMPTS: usize = 10_000;
+
+    fn new(size: usize) -> Edges {
+        Edges {
+            size,
+            seen: HashSet::new(),
+        }
+    }
+
+    fn next(&mut self, start: u64, end: u64, rng: &mut Rng) -> Option<u64> {
+        let size = self.size;
+        let inner = !0 >> (64 - (size - 2));
+        for _ in 0..Self::ATTEMPTS {
+            let bits = start | (rng.next() & inner) << 1 | end << (size - 1);
+            let reversed = bits.reverse_bits() >> (64 - size);
+            if bits != reversed && !self.seen.contains(&bits) && !self.seen.contains(&reversed) {
+                self.seen.insert(bits);
+                self.seen.insert(reversed);
+                return Some(bits);
+            }
+        }
+        None
+    }
+}
+
+// Draws `count` non-overlapping copies of `pattern` into the image.
+fn embed(image: &mut Image, pattern: &Pattern, count: usize, rng: &mut Rng) -> Result<Vec<Match>> {
+    let mut placed = Vec::new();
+    let mut taken = HashSet::new();
+    let mut attempts = 0;
+    while placed.len() < count {
+        attempts += 1;
+        if attempts > 1000 * count {
+            return Err(Error::PatternDoesNotFit(count));
+        }
+        let (orientation, offsets) = &pattern.variants[rng.below(pattern.variants.len())];
+        let rows = offsets.iter().map(|&(r, _)| r + 1).max().unwrap();
+        let cols = offsets.iter().map(|&(_, c)| c + 1).max().unwrap();
+        if rows > image.height() || cols > image.width() {
+            continue;
+        }
+        let (row, col) = (
+            rng.below(image.height() - rows + 1),
+            rng.below(image.width() - cols + 1),
+        );
+        let cells = offsets
+            .iter()
+            .map(|(r, c)| (row + r, col + c))
+            .collect::<Vec<_>>();
+        if cells.iter().any(|cell| taken.contains(cell)) {
+            continue;
+        }
+        for &(r, c) in cells.iter() {
+            image.0[r][c] = 0;
+        }
+        taken.extend(cells);
+        placed.push(Match {
+            row,
+            col,
+            orientation: *orientation,
+        });
+    }
+    Ok(placed)
+}
+
+impl std::fmt::Display for Puzzle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.tiles.iter().join("\n\n"))
+    }
+}
+
+impl Puzzle {
+    pub fn tiles(&self) -> Tiles {
+        Tiles::new(&self.tiles)
+    }
+
+    pub fn solution(&self) -> &[Vec<TileId>] {
+        &self.solution
+    }
+
+    // The picture with tile borders removed, in the solution's orientation.
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    pub fn monsters(&self) -> &[Match] {
+        &self.monsters
+    }
+
+    // Whether `assembly` is the solution, allowing for the whole puzzle
+    // being rotated or flipped.
+    pub fn is_solution(&self, assembly: &Assembly) -> bool {
+        symmetries(&self.solution).contains(&assembly.ids())
+    }
+
+    // The orientation of `assembly` relative to the solution, if it is one.
+    pub fn orientation(&self, assembly: &Assembly) -> Option<Orientation> {
+        symmetries(&self.solution)
+            .iter()
+            .position(|ids| *ids == assembly.ids())
+            .map(Orientation::from_symmetry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::MatchMode;
+    use super::*;
+
+    fn cells(image: &Image) -> usize {
+        image.0.iter().flatten().filter(|&&v| v != -1).count()
+    }
+
+    #[test]
+    fn test_noise_round_trip() {
+        let puzzle = Generator::new(12, 12).monsters(3).seed(5).noise().unwrap();
+        let tiles = puzzle.to_string().parse::<Tiles>().unwrap();
+        assert_eq!(144, tiles.len());
+        assert!(puzzle.to_string().starts_with("Tile "));
+
+        let assembly = tiles.solve(12, 12).unwrap();
+        assert!(puzzle.is_solution(&assembly));
+        assert!(puzzle.orientation(&assembly).is_some());
+        let mut image = assembly.image();
+        assert!(assembly.images().contains(puzzle.image()));
+        assert_eq!(3, image.mark_sea_monsters());
+        assert_eq!(cells(puzzle.image()) - 3 * 15, image.roughness());
+    }
+
+    #[test]
+    fn test_from_image() {
+        let image = ["#.#.#.", ".#.#.#", "##..##", "..##.."]
+            .iter()
+            .cycle()
+            .take(12)
+            .map(|line| line.repeat(3))
+            .join("\n")
+            .parse::<Image>()
+            .unwrap();
+        let generator = Generator::new(3, 2).tile_size(8).seed(9);
+        let puzzle = generator.from_image(&image).unwrap();
+        assert_eq!(&image, puzzle.image());
+        let assembly = puzzle.tiles().solve(3, 2).unwrap();
+        assert!(puzzle.is_solution(&assembly));
+        assert!(assembly.images().contains(&image));
+
+        assert!(matches!(
+            Generator::new(2, 2).tile_size(8).from_image(&image),
+            Err(Error::ImageSize {
+                got: (12, 18),
+                want: (12, 12)
+            })
+        ));
+        assert!(matches!(
+            Generator::new(2, 2).tile_size(2).noise(),
+            Err(Error::InvalidTileSize(2))
+        ));
+        assert!(matches!(
+            Generator::new(2, 2).monsters(1).noise(),
+            Err(Error::PatternDoesNotFit(1))
+        ));
+        assert!(matches!(
+            Generator::new(20, 20).tile_size(4).noise(),
+            Err(Error::TooManyTiles {
+                tiles: 400,
+                size: 4
+            })
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "expensive_tests"), ignore)]
+    fn test_round_trip_many() {
+        let mut rng = Rng::new(2020);
+        for seed in 0..100 {
+            let (width, height) = (1 + rng.below(12), 1 + rng.below(12));
+            let monsters = rng.below(width * height / 8 + 1);
+            let puzzle = Generator::new(width, height)
+                .tile_size(10 + rng.below(4))
+                .monsters(monsters)
+                .density(0.2)
+                .seed(seed)
+                .noise()
+                .unwrap();
+            let tiles = puzzle.to_string().parse::<Tiles>().unwrap();
+            let assembly = tiles.solve(width, height).unwrap();
+            assert!(puzzle.is_solution(&assembly), "seed {}", seed);
+            assert!(assembly.images().contains(puzzle.image()));
+            // Noise can complete extra monsters, but never hides one.
+            let found = puzzle
+                .image()
+                .find(&Pattern::sea_monster(), MatchMode::Overlapping);
+            assert_eq!(monsters, puzzle.monsters().len());
+            assert!(puzzle.monsters().iter().all(|m| found.contains(m)));
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use super::Assembly;
+use super::Error;
+use super::Image;
+use super::Result;
+use super::TileId;
+use itertools::Itertools;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    // Pixels per image cell; SVG output is scaled the same way.
+    pub scale: usize,
+    pub borders: bool,
+    pub labels: bool,
+    pub highlight: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shade {
+    Water,
+    Rough,
+    Monster,
+    Border,
+    Label,
+}
+
+// Where a tile's interior ended up in the image, in cells.
+#[derive(Debug, Clone, Copy)]
+struct Region {
+    id: TileId,
+    row: usize,
+    col: usize,
+    height: usize,
+    width: usize,
+}
+
+// An image, plus the tiles it was assembled from if known, ready to export.
+pub struct Canvas {
+    cells: Vec<Vec<i8>>,
+    regions: Vec<Region>,
+}
+
+// 3x5 glyphs for the digits in tile labels, one row per entry with the
+// leftmost pixel in the highest bit.
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            scale: 4,
+            borders: true,
+            labels: true,
+            highlight: true,
+        }
+    }
+}
+
+impl Shade {
+    fn grey(self) -> u8 {
+        match self {
+            Shade::Water => 0,
+            Shade::Rough => 140,
+            Shade::Monster => 255,
+            Shade::Border => 80,
+            Shade::Label => 255,
+        }
+    }
+
+    fn rgb(self) -> [u8; 3] {
+        match self {
+            Shade::Water => [0, 40, 90],
+            Shade::Rough => [90, 160, 200],
+            Shade::Monster => [230, 60, 40],
+            Shade::Border => [255, 220, 0],
+            Shade::Label => [255, 255, 255],
+        }
+    }
+
+    fn hex(self) -> String {
+        let [r, g, b] = self.rgb();
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+// Writes a tile's ID in its top-left corner, clipped to the tile. Glyph
+// pixels are half a cell so that a four digit ID fits across a standard
+// eight cell tile.
+fn draw_label(pixels: &mut [Vec<Shade>], r: &Region, scale: usize) {
+    let size = (scale / 2).max(1);
+    let (top, left) = (r.row * scale + 2, r.col * scale + 2);
+    let (bottom, right) = (
+        (r.row + r.height) * scale - 1,
+        (r.col + r.width) * scale - 1,
+    );
+    for (i, digit) in r.id.to_string().bytes().enumerate() {
+        let glyph = DIGITS[(digit - b'0') as usize];
+        for (gy, gx) in (0..5).cartesian_product(0..3) {
+            if glyph[gy] >> (2 - gx) & 1 == 0 {
+                continue;
+            }
+            let (y0, x0) = (top + gy * size, left + (i * 4 + gx) * size);
+            for (y, x) in (y0..y0 + size).cartesian_product(x0..x0 + size) {
+                if y < bottom && x < right {
+                    pixels[y][x] = Shade::Label;
+                }
+            }
+        }
+    }
+}
+
+impl Canvas {
+    pub fn from_image(image: &Image) -> Canvas {
+        Canvas {
+            cells: image.0.clone(),
+            regions: Vec::new(),
+        }
+    }
+
+    // `image` must be in the same orientation as `assembly`, such as the
+    // result of `Assembly::image` after marking. Every tile needs at least one
+    // interior cell once its border is removed.
+    pub fn from_assembly(assembly: &Assembly, image: &Image) -> Result<Canvas> {
+        let interior = |len: usize| match len.checked_sub(2) {
+            Some(n) if n > 0 => Ok(n),
+            _ => Err(Error::InvalidTileSize(len)),
+        };
+        let mut regions = Vec::new();
+        let mut row = 0;
+        let mut width = 0;
+        for tiles in assembly.0.iter() {
+            let mut col = 0;
+            let height = interior(tiles[0].data.len())?;
+            for tile in tiles {
+                let tile_width = interior(tile.data[0].len())?;
+                regions.push(Region {
+                    id: tile.id,
+                    row,
+                    col,
+                    height,
+                    width: tile_width,
+                });
+                col += tile_width;
+            }
+            row += height;
+            width = col;
+        }
+        let got = (image.height(), image.width());
+        if got != (row, width) {
+            return Err(Error::ImageSize {
+                got,
+                want: (row, width),
+            });
+        }
+        Ok(Canvas {
+            cells: image.0.clone(),
+            regions,
+        })
+    }
+
+    fn height(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn width(&self) -> usize {
+        self.cells.first().map_or(0, Vec::len)
+    }
+
+    fn shade(&self, row: usize, col: usize, options: &Options) -> Shade {
+        match self.cells[row][col] {
+            -1 => Shade::Water,
+            0 => Shade::Rough,
+            _ if options.highlight => Shade::Monster,
+            _ => Shade::Rough,
+        }
+    }
+
+    fn raster(&self, options: &Options) -> Vec<Vec<Shade>> {
+        let s = options.scale.max(1);
+        let mut pixels = vec![vec![Shade::Water; self.width() * s]; self.height() * s];
+        for (y, line) in pixels.iter_mut().enumerate() {
+            for (x, pixel) in line.iter_mut().enumerate() {
+                *pixel = self.shade(y / s, x / s, options);
+            }
+        }
+        for r in self.regions.iter() {
+            let (top, left) = (r.row * s, r.col * s);
+            let (bottom, right) = ((r.row + r.height) * s - 1, (r.col + r.width) * s - 1);
+            if options.borders {
+                for y in [top, bottom].iter() {
+                    for pixel in pixels[*y][left..=right].iter_mut() {
+                        *pixel = Shade::Border;
+                    }
+                }
+                for line in pixels[top..=bottom].iter_mut() {
+                    line[left] = Shade::Border;
+                    line[right] = Shade::Border;
+                }
+            }
+            if options.labels {
+                draw_label(&mut pixels, r, s);
+            }
+        }
+        pixels
+    }
+
+    fn netpbm(&self, magic: &str, options: &Options, encode: fn(Shade) -> Vec<u8>) -> Vec<u8> {
+        let pixels = self.raster(options);
+        let mut out = format!(
+            "{}\n{} {}\n255\n",
+            magic,
+            pixels.first().map_or(0, Vec::len),
+            pixels.len()
+        )
+        .into_bytes();
+        out.extend(pixels.iter().flatten().flat_map(|&p| encode(p)));
+        out
+    }
+
+    // Binary greyscale PGM (P5).
+    pub fn to_pgm(&self, options: &Options) -> Vec<u8> {
+        self.netpbm("P5", options, |p| vec![p.grey()])
+    }
+
+    // Binary colour PPM (P6).
+    pub fn to_ppm(&self, options: &Options) -> Vec<u8> {
+        self.netpbm("P6", options, |p| p.rgb().to_vec())
+    }
+
+    pub fn to_svg(&self, options: &Options) -> String {
+        let (height, width) = (self.height(), self.width());
+        let s = options.scale.max(1);
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+            width * s,
+            height * s,
+            width,
+            height
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r#"<rect width="{}" height="{}" fill="{}"/>"#,
+            width,
+            height,
+            Shade::Water.hex()
+        )
+        .unwrap();
+        // One rectangle per horizontal run of same-shaded cells.
+        for row in 0..height {
+            let mut col = 0;
+            while col < width {
+                let shade = self.shade(row, col, options);
+                let run = (col..width)
+                    .take_while(|&c| self.shade(row, c, options) == shade)
+                    .count();
+                if shade != Shade::Water {
+                    writeln!(
+                        svg,
+                        r#"<rect x="{}" y="{}" width="{}" height="1" fill="{}"/>"#,
+                        col,
+                        row,
+                        run,
+                        shade.hex()
+                    )
+                    .unwrap();
+                }
+                col += run;
+            }
+        }
+        for r in self.regions.iter() {
+            if options.borders {
+                writeln!(
+                    svg,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="{}" stroke-width="0.1"/>"#,
+                    r.col,
+                    r.row,
+                    r.width,
+                    r.height,
+                    Shade::Border.hex()
+                )
+                .unwrap();
+            }
+            if options.labels {
+                writeln!(
+                    svg,
+                    r#"<text x="{}.5" y="{}.5" font-family="monospace" font-size="1.5" fill="{}">{}</text>"#,
+                    r.col,
+                    r.row + 1,
+                    Shade::Label.hex(),
+                    r.id
+                )
+                .unwrap();
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::MatchMode;
+    use super::super::Pattern;
+    use super::super::Tiles;
+    use super::*;
+
+    static TWO_TILES: &str = r"Tile 1:
+#..#
+.#.#
+..##
+#...
+
+Tile 2:
+#...
+##.#
+#..#
+.#..";
+
+    fn plain() -> Options {
+        Options {
+            scale: 2,
+            borders: false,
+            labels: false,
+            highlight: true,
+        }
+    }
+
+    #[test]
+    fn test_netpbm() {
+        let mut image = "##.\n..#".parse::<Image>().unwrap();
+        image.mark(&"##".parse::<Pattern>().unwrap(), MatchMode::Overlapping);
+        let canvas = Canvas::from_image(&image);
+
+        let pgm = canvas.to_pgm(&plain());
+        let header = b"P5\n6 4\n255\n";
+        assert_eq!(header.len() + 24, pgm.len());
+        assert_eq!(&pgm[..header.len()], header);
+        assert_eq!(Shade::Monster.grey(), pgm[header.len()]);
+        assert_eq!(Shade::Water.grey(), pgm[header.len() + 4]);
+
+        let ppm = canvas.to_ppm(&Options {
+            highlight: false,
+            ..plain()
+        });
+        let header = b"P6\n6 4\n255\n";
+        assert_eq!(header.len() + 24 * 3, ppm.len());
+        assert_eq!(&Shade::Rough.rgb(), &ppm[header.len()..header.len() + 3]);
+    }
+
+    #[test]
+    fn test_assembly_export() {
+        let assembly = TWO_TILES.parse::<Tiles>().unwrap().assemble(2, 1).unwrap();
+        let canvas = Canvas::from_assembly(&assembly, &assembly.image()).unwrap();
+
+        let svg = canvas.to_svg(&Options::default());
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(">1</text>") && svg.contains(">2</text>"));
+        assert_eq!(2, svg.matches(r#"fill="none""#).count());
+        assert!(!canvas.to_svg(&plain()).contains("<text"));
+
+        let pixels = canvas.raster(&Options::default());
+        assert_eq!((8, 16), (pixels.len(), pixels[0].len()));
+        assert_eq!(Shade::Border, pixels[0][8]);
+        assert_eq!(Shade::Label, pixels[2][4]);
+
+        assert!(matches!(
+            Canvas::from_assembly(&assembly, &"#".parse::<Image>().unwrap()),
+            Err(Error::ImageSize {
+                got: (1, 1),
+                want: (2, 4)
+            })
+        ));
+
+        let scaled = canvas.raster(&Options {
+            scale: 0,
+            ..Options::default()
+        });
+        assert_eq!((2, 4), (scaled.len(), scaled[0].len()));
+    }
+
+    #[test]
+    fn test_tiles_without_interior() {
+        for tile in ["Tile 1:\n##\n#.", "Tile 1:\n#"].iter() {
+            let assembly = tile.parse::<Tiles>().unwrap().assemble(1, 1).unwrap();
+            assert!(matches!(
+                Canvas::from_assembly(&assembly, &assembly.image()),
+                Err(Error::InvalidTileSize(_))
+            ));
+        }
+    }
+}
@@ -1,4 +1,6 @@
 pub mod airplane;
+pub mod animation;
+pub mod automaton;
 pub mod cards;
 pub mod charger;
 pub mod credentials;
@@ -1,8 +1,45 @@
-use std::collections::VecDeque;
+use num_bigint::BigInt;
+use num_bigint::Sign;
+use std::collections::HashMap;
 use thiserror::Error as ThisError;
 
-#[derive(Debug, ThisError)]
-pub enum Error {}
+#[derive(Debug, PartialEq, ThisError)]
+pub enum Error {
+    #[error("unexpected character '{c}' at column {column}")]
+    UnexpectedChar { c: char, column: usize },
+
+    #[error("invalid number '{number}' at column {column}")]
+    InvalidNumber { number: String, column: usize },
+
+    #[error("unexpected '{token}' at column {column}")]
+    UnexpectedToken { token: String, column: usize },
+
+    #[error("unexpected end of expression")]
+    UnexpectedEnd,
+
+    #[error("parenthesis at column {0} is never closed")]
+    UnclosedParen(usize),
+
+    #[error("no precedence defined for operator '{0}'")]
+    UnknownOperator(Op),
+
+    #[error("literal {0} is out of range")]
+    LiteralOutOfRange(String),
+
+    #[error("arithmetic overflow evaluating '{0}'")]
+    Overflow(Op),
+
+    #[error("overflow negating {0}")]
+    NegationOverflow(String),
+
+    #[error("division by zero")]
+    DivisionByZero,
+
+    #[error("exponent {0} is negative or too large")]
+    InvalidExponent(String),
+}
+
+type Result<T> = std::result::Result<T, Error>;
 
 #[derive(PartialEq, Eq, Debug)]
 enum Token {
@@ -12,64 +49,230 @@ enum Token {
     CloseParen,
 }
 
-#[derive(PartialEq, Eq, Debug, Clone)]
-enum Op {
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum Op {
     Add,
+    Sub,
     Mul,
+    Div,
+    Rem,
+    Pow,
 }
 
-pub struct Expr {
-    tokens: Vec<Token>,
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Associativity {
+    Left,
+    Right,
 }
 
-struct Context {
-    acc: u64,
-    op: Option<Op>,
+// Binding strength of each operator; higher precedence binds tighter.
+#[derive(Clone, Debug, Default)]
+pub struct PrecedenceTable {
+    levels: HashMap<Op, (u32, Associativity)>,
+    unary: Option<u32>,
 }
 
-struct AdvancedContext {
-    acc: u64,
-    op: Option<Op>,
-    mul_stack: VecDeque<u64>,
+// Integer types expressions can be evaluated as. Every operation is checked,
+// so overflow is reported rather than wrapping. `BigInt` never overflows,
+// except for powers too large to be worth computing.
+pub trait Number: Clone + PartialEq + std::fmt::Debug + std::fmt::Display {
+    fn from_u64(n: u64) -> Option<Self>;
+    fn from_negated_u64(n: u64) -> Option<Self>;
+    fn to_u32(&self) -> Option<u32>;
+    fn is_zero(&self) -> bool;
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+    fn checked_sub(self, rhs: Self) -> Option<Self>;
+    fn checked_mul(self, rhs: Self) -> Option<Self>;
+    fn checked_div(self, rhs: Self) -> Option<Self>;
+    fn checked_rem(self, rhs: Self) -> Option<Self>;
+    fn checked_pow(self, exp: u32) -> Option<Self>;
+    fn checked_neg(self) -> Option<Self>;
 }
 
-struct NumBuilder {
-    acc: Option<u64>,
+macro_rules! impl_number {
+    ($($t:ty),*) => {
+        $(impl Number for $t {
+            fn from_u64(n: u64) -> Option<Self> {
+                std::convert::TryFrom::try_from(n).ok()
+            }
+            fn from_negated_u64(n: u64) -> Option<Self> {
+                std::convert::TryFrom::try_from(-i128::from(n)).ok()
+            }
+            fn to_u32(&self) -> Option<u32> {
+                std::convert::TryFrom::try_from(*self).ok()
+            }
+            fn is_zero(&self) -> bool {
+                *self == 0
+            }
+            fn checked_add(self, rhs: Self) -> Option<Self> {
+                <$t>::checked_add(self, rhs)
+            }
+            fn checked_sub(self, rhs: Self) -> Option<Self> {
+                <$t>::checked_sub(self, rhs)
+            }
+            fn checked_mul(self, rhs: Self) -> Option<Self> {
+                <$t>::checked_mul(self, rhs)
+            }
+            fn checked_div(self, rhs: Self) -> Option<Self> {
+                <$t>::checked_div(self, rhs)
+            }
+            fn checked_rem(self, rhs: Self) -> Option<Self> {
+                <$t>::checked_rem(self, rhs)
+            }
+            fn checked_pow(self, exp: u32) -> Option<Self> {
+                <$t>::checked_pow(self, exp)
+            }
+            fn checked_neg(self) -> Option<Self> {
+                <$t>::checked_neg(self)
+            }
+        })*
+    };
 }
 
-impl Context {
-    fn new() -> Context {
-        Context { acc: 0, op: None }
+impl_number!(i64, i128);
+
+// The most bits a `BigInt` power may have, about 300,000 decimal digits.
+const MAX_POW_BITS: u64 = 1 << 20;
+
+impl Number for BigInt {
+    fn from_u64(n: u64) -> Option<Self> {
+        Some(BigInt::from(n))
+    }
+    fn from_negated_u64(n: u64) -> Option<Self> {
+        Some(-BigInt::from(n))
+    }
+    fn to_u32(&self) -> Option<u32> {
+        std::convert::TryFrom::try_from(self).ok()
+    }
+    fn is_zero(&self) -> bool {
+        self.sign() == Sign::NoSign
+    }
+    fn checked_add(self, rhs: Self) -> Option<Self> {
+        Some(self + rhs)
+    }
+    fn checked_sub(self, rhs: Self) -> Option<Self> {
+        Some(self - rhs)
+    }
+    fn checked_mul(self, rhs: Self) -> Option<Self> {
+        Some(self * rhs)
+    }
+    fn checked_div(self, rhs: Self) -> Option<Self> {
+        Some(self).filter(|_| !rhs.is_zero()).map(|lhs| lhs / rhs)
+    }
+    fn checked_rem(self, rhs: Self) -> Option<Self> {
+        Some(self).filter(|_| !rhs.is_zero()).map(|lhs| lhs % rhs)
+    }
+    fn checked_pow(self, exp: u32) -> Option<Self> {
+        match self.bits().saturating_mul(u64::from(exp)) {
+            bits if bits > MAX_POW_BITS => None,
+            _ => Some(self.pow(exp)),
+        }
+    }
+    fn checked_neg(self) -> Option<Self> {
+        Some(-self)
     }
 }
 
-impl AdvancedContext {
-    fn new() -> AdvancedContext {
-        AdvancedContext {
-            acc: 0,
-            op: None,
-            mul_stack: VecDeque::new(),
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Ast {
+    Num(u64),
+    Neg(Box<Ast>),
+    BinOp {
+        op: Op,
+        lhs: Box<Ast>,
+        rhs: Box<Ast>,
+    },
+}
+
+pub struct Expr {
+    tokens: Vec<Token>,
+    columns: Vec<usize>,
+}
+
+struct Parser<'a> {
+    expr: &'a Expr,
+    table: &'a PrecedenceTable,
+    pos: usize,
+}
+
+impl std::fmt::Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Op::Add => write!(f, "+"),
+            Op::Sub => write!(f, "-"),
+            Op::Mul => write!(f, "*"),
+            Op::Div => write!(f, "/"),
+            Op::Rem => write!(f, "%"),
+            Op::Pow => write!(f, "^"),
+        }
+    }
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Num(n) => write!(f, "{}", n),
+            Token::Op(op) => write!(f, "{}", op),
+            Token::OpenParen => write!(f, "("),
+            Token::CloseParen => write!(f, ")"),
         }
     }
 }
 
-impl NumBuilder {
-    fn new() -> NumBuilder {
-        NumBuilder { acc: None }
+impl PrecedenceTable {
+    pub fn new() -> PrecedenceTable {
+        Default::default()
+    }
+
+    pub fn with(mut self, op: Op, precedence: u32, associativity: Associativity) -> Self {
+        self.levels.insert(op, (precedence, associativity));
+        self
     }
 
-    fn push_digit(&mut self, c: char) {
-        let d = c.to_digit(10).unwrap() as u64;
-        match self.acc {
-            None => self.acc = Some(d),
-            Some(i) => self.acc = Some(10 * i + d),
+    // Precedence of unary minus; by default it binds tighter than any binary
+    // operator.
+    pub fn with_unary(mut self, precedence: u32) -> Self {
+        self.unary = Some(precedence);
+        self
+    }
+
+    pub fn get(&self, op: Op) -> Option<(u32, Associativity)> {
+        self.levels.get(&op).copied()
+    }
+
+    pub fn unary(&self) -> u32 {
+        self.unary.unwrap_or(u32::MAX)
+    }
+
+    fn with_all(mut self, ops: &[Op], precedence: u32, associativity: Associativity) -> Self {
+        for op in ops.iter() {
+            self = self.with(*op, precedence, associativity);
         }
+        self
     }
 
-    fn pop_reset(&mut self) -> Option<u64> {
-        let temp = self.acc;
-        self.acc = None;
-        temp
+    // All operators bind equally and are evaluated left to right.
+    pub fn left_to_right() -> PrecedenceTable {
+        PrecedenceTable::new().with_all(
+            &[Op::Add, Op::Sub, Op::Mul, Op::Div, Op::Rem, Op::Pow],
+            1,
+            Associativity::Left,
+        )
+    }
+
+    pub fn addition_first() -> PrecedenceTable {
+        PrecedenceTable::new()
+            .with_all(&[Op::Add, Op::Sub], 2, Associativity::Left)
+            .with_all(&[Op::Mul, Op::Div, Op::Rem], 1, Associativity::Left)
+            .with(Op::Pow, 3, Associativity::Right)
+    }
+
+    pub fn standard() -> PrecedenceTable {
+        PrecedenceTable::new()
+            .with_all(&[Op::Add, Op::Sub], 1, Associativity::Left)
+            .with_all(&[Op::Mul, Op::Div, Op::Rem], 2, Associativity::Left)
+            .with_unary(3)
+            .with(Op::Pow, 4, Associativity::Right)
     }
 }
 
@@ -78,131 +281,231 @@ impl std::str::FromStr for Expr {
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         let mut tokens = Vec::new();
-        let mut num_builder = NumBuilder::new();
-        let mut chars_iter = s.chars();
-        let mut c = chars_iter.next();
-        loop {
-            match c {
-                None => {
-                    if let Some(num) = num_builder.pop_reset() {
-                        tokens.push(Token::Num(num))
-                    };
-                    return Ok(Expr { tokens });
-                }
-                Some(c) => match c {
-                    '(' => tokens.push(Token::OpenParen),
-                    ')' => {
-                        if let Some(num) = num_builder.pop_reset() {
-                            tokens.push(Token::Num(num))
-                        };
-                        tokens.push(Token::CloseParen);
-                    }
-                    '0'..='9' => num_builder.push_digit(c),
-                    '+' => {
-                        if let Some(num) = num_builder.pop_reset() {
-                            tokens.push(Token::Num(num))
-                        };
-                        tokens.push(Token::Op(Op::Add));
+        let mut columns = Vec::new();
+        let mut chars = s.chars().enumerate().peekable();
+        while let Some((i, c)) = chars.next() {
+            let token = match c {
+                '(' => Token::OpenParen,
+                ')' => Token::CloseParen,
+                '+' => Token::Op(Op::Add),
+                '-' => Token::Op(Op::Sub),
+                '*' => Token::Op(Op::Mul),
+                '/' => Token::Op(Op::Div),
+                '%' => Token::Op(Op::Rem),
+                '^' => Token::Op(Op::Pow),
+                '0'..='9' => {
+                    let mut number = c.to_string();
+                    while let Some((_, d)) = chars.next_if(|(_, d)| d.is_ascii_digit()) {
+                        number.push(d);
                     }
-                    '*' => {
-                        if let Some(num) = num_builder.pop_reset() {
-                            tokens.push(Token::Num(num))
-                        };
-                        tokens.push(Token::Op(Op::Mul));
+                    Token::Num(number.parse().map_err(|_| Error::InvalidNumber {
+                        number,
+                        column: i + 1,
+                    })?)
+                }
+                c if c.is_whitespace() => continue,
+                _ => return Err(Error::UnexpectedChar { c, column: i + 1 }),
+            };
+            tokens.push(token);
+            columns.push(i + 1);
+        }
+        let expr = Expr { tokens, columns };
+        // Structural errors don't depend on precedence, so catch them here.
+        expr.parse(&PrecedenceTable::left_to_right())?;
+        Ok(expr)
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.expr.tokens.get(self.pos)
+    }
+
+    fn unexpected(&self) -> Error {
+        match self.peek() {
+            Some(token) => Error::UnexpectedToken {
+                token: token.to_string(),
+                column: self.expr.columns[self.pos],
+            },
+            None => Error::UnexpectedEnd,
+        }
+    }
+
+    // Precedence climbing: parses operands joined by operators that bind at
+    // least as tightly as `min_precedence`.
+    fn parse_expr(&mut self, min_precedence: u32) -> Result<Ast> {
+        let mut lhs = self.parse_primary()?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let (precedence, associativity) =
+                self.table.get(*op).ok_or(Error::UnknownOperator(*op))?;
+            if precedence < min_precedence {
+                break;
+            }
+            self.pos += 1;
+            // Nothing binds tighter than `u32::MAX`, so that leaves a lone
+            // operand.
+            let rhs = match (associativity, precedence.checked_add(1)) {
+                (Associativity::Left, Some(tighter)) => self.parse_expr(tighter)?,
+                (Associativity::Left, None) => self.parse_primary()?,
+                (Associativity::Right, _) => self.parse_expr(precedence)?,
+            };
+            lhs = Ast::BinOp {
+                op: *op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn parse_primary(&mut self) -> Result<Ast> {
+        match self.peek() {
+            Some(Token::Num(n)) => {
+                self.pos += 1;
+                Ok(Ast::Num(*n))
+            }
+            Some(Token::Op(Op::Sub)) => {
+                self.pos += 1;
+                let operand = self.parse_expr(self.table.unary())?;
+                Ok(Ast::Neg(Box::new(operand)))
+            }
+            Some(Token::OpenParen) => {
+                let column = self.expr.columns[self.pos];
+                self.pos += 1;
+                let inner = self.parse_expr(0)?;
+                match self.peek() {
+                    Some(Token::CloseParen) => {
+                        self.pos += 1;
+                        Ok(inner)
                     }
-                    ' ' => (),
-                    _ => panic!("unexpected character: {}", c),
-                },
+                    None => Err(Error::UnclosedParen(column)),
+                    _ => Err(self.unexpected()),
+                }
             }
-            c = chars_iter.next();
+            _ => Err(self.unexpected()),
         }
     }
 }
 
 impl Expr {
-    pub fn result(&self) -> u64 {
-        let mut ctx = Context::new();
-        let mut stack = VecDeque::new();
-
-        for token in &self.tokens {
-            match token {
-                Token::Num(n) => {
-                    match ctx.op {
-                        None => ctx.acc = *n,
-                        Some(Op::Add) => {
-                            ctx.acc += n;
-                            ctx.op = None;
-                        }
-                        Some(Op::Mul) => {
-                            ctx.acc *= n;
-                            ctx.op = None;
-                        }
-                    };
-                }
-                Token::Op(o) => ctx.op = Some(o.clone()),
-                Token::OpenParen => {
-                    stack.push_front(ctx);
-                    ctx = Context::new();
-                }
-                Token::CloseParen => {
-                    let total = ctx.acc;
-                    ctx = stack.pop_front().unwrap();
-                    match ctx.op {
-                        None => ctx.acc = total,
-                        Some(Op::Add) => ctx.acc += total,
-                        Some(Op::Mul) => ctx.acc *= total,
-                    };
-                }
-            }
+    pub fn parse(&self, table: &PrecedenceTable) -> Result<Ast> {
+        let mut parser = Parser {
+            expr: self,
+            table,
+            pos: 0,
+        };
+        let ast = parser.parse_expr(0)?;
+        if parser.pos < self.tokens.len() {
+            return Err(parser.unexpected());
         }
-        ctx.acc
+        Ok(ast)
+    }
+
+    pub fn evaluate<N: Number>(&self, table: &PrecedenceTable) -> Result<N> {
+        self.parse(table)?.evaluate()
+    }
+
+    pub fn result(&self) -> u64 {
+        self.unsigned_result(&PrecedenceTable::left_to_right())
     }
 
     pub fn advanced_result(&self) -> u64 {
-        let mut ctx = AdvancedContext::new();
-        let mut stack = VecDeque::new();
-
-        for token in &self.tokens {
-            match token {
-                Token::Num(n) => {
-                    match ctx.op {
-                        None => ctx.acc = *n,
-                        Some(Op::Add) => {
-                            ctx.acc += n;
-                            ctx.op = None;
-                        }
-                        Some(Op::Mul) => panic!("unexpected mul op"),
-                    };
-                }
-                Token::Op(Op::Add) => ctx.op = Some(Op::Add),
-                Token::Op(Op::Mul) => {
-                    ctx.mul_stack.push_front(ctx.acc);
-                    ctx.acc = 0;
-                    ctx.op = None;
-                }
+        self.unsigned_result(&PrecedenceTable::addition_first())
+    }
 
-                Token::OpenParen => {
-                    stack.push_front(ctx);
-                    ctx = AdvancedContext::new();
-                }
-                Token::CloseParen => {
-                    let mut total = ctx.acc;
-                    while !ctx.mul_stack.is_empty() {
-                        total *= ctx.mul_stack.pop_front().unwrap();
-                    }
-                    ctx = stack.pop_front().unwrap();
-                    match ctx.op {
-                        None => ctx.acc = total,
-                        Some(Op::Add) => ctx.acc += total,
-                        Some(Op::Mul) => ctx.mul_stack.push_front(total),
-                    };
+    // Evaluates as `BigInt`, so intermediate values may exceed u64 as long as
+    // the final result fits.
+    fn unsigned_result(&self, table: &PrecedenceTable) -> u64 {
+        let result = self
+            .evaluate::<BigInt>(table)
+            .unwrap_or_else(|e| panic!("can't evaluate expression: {}", e));
+        std::convert::TryFrom::try_from(&result)
+            .unwrap_or_else(|_| panic!("result out of range: {}", result))
+    }
+}
+
+impl Ast {
+    pub fn evaluate<N: Number>(&self) -> Result<N> {
+        let (op, lhs, rhs) = match self {
+            Ast::Num(n) => {
+                return N::from_u64(*n).ok_or_else(|| Error::LiteralOutOfRange(n.to_string()))
+            }
+            Ast::Neg(operand) => {
+                // Negated literals are range checked as a whole so that the
+                // most negative value of N can be written.
+                if let Ast::Num(n) = **operand {
+                    return N::from_negated_u64(n)
+                        .ok_or_else(|| Error::LiteralOutOfRange(format!("-{}", n)));
                 }
+                let val = operand.evaluate::<N>()?;
+                return val
+                    .clone()
+                    .checked_neg()
+                    .ok_or_else(|| Error::NegationOverflow(val.to_string()));
             }
+            Ast::BinOp { op, lhs, rhs } => (*op, lhs.evaluate::<N>()?, rhs.evaluate::<N>()?),
+        };
+        match op {
+            Op::Div | Op::Rem if rhs.is_zero() => return Err(Error::DivisionByZero),
+            _ => (),
         }
-        while !ctx.mul_stack.is_empty() {
-            ctx.acc *= ctx.mul_stack.pop_front().unwrap();
+        let result = match op {
+            Op::Add => lhs.checked_add(rhs),
+            Op::Sub => lhs.checked_sub(rhs),
+            Op::Mul => lhs.checked_mul(rhs),
+            Op::Div => lhs.checked_div(rhs),
+            Op::Rem => lhs.checked_rem(rhs),
+            Op::Pow => {
+                let exp = rhs
+                    .to_u32()
+                    .ok_or_else(|| Error::InvalidExponent(rhs.to_string()))?;
+                lhs.checked_pow(exp)
+            }
+        };
+        result.ok_or(Error::Overflow(op))
+    }
+
+    // Prints the expression with only the parentheses needed for it to parse
+    // back into the same tree under `table`.
+    pub fn pretty(&self, table: &PrecedenceTable) -> String {
+        match self {
+            Ast::Num(n) => n.to_string(),
+            Ast::Neg(operand) => match **operand {
+                Ast::Num(_) => format!("-{}", operand.pretty(table)),
+                Ast::Neg(_) => format!("-({})", operand.pretty(table)),
+                Ast::BinOp { op, .. } => match table.get(op) {
+                    Some((precedence, _)) if precedence > table.unary() => {
+                        format!("-{}", operand.pretty(table))
+                    }
+                    _ => format!("-({})", operand.pretty(table)),
+                },
+            },
+            Ast::BinOp { op, lhs, rhs } => format!(
+                "{} {} {}",
+                lhs.operand(*op, Associativity::Left, table),
+                op,
+                rhs.operand(*op, Associativity::Right, table)
+            ),
+        }
+    }
+
+    fn operand(&self, parent: Op, side: Associativity, table: &PrecedenceTable) -> String {
+        let child = match self {
+            Ast::BinOp { op, .. } => table.get(*op),
+            Ast::Neg(_) => Some((table.unary(), side)),
+            Ast::Num(_) => return self.pretty(table),
+        };
+        let needs_parens = match (table.get(parent), child) {
+            (Some((parent_prec, assoc)), Some((child_prec, _))) => {
+                child_prec < parent_prec || (child_prec == parent_prec && assoc != side)
+            }
+            _ => true,
+        };
+        if needs_parens {
+            format!("({})", self.pretty(table))
+        } else {
+            self.pretty(table)
         }
-        ctx.acc
     }
 }
 
@@ -320,4 +623,188 @@ mod tests {
                 .advanced_result()
         );
     }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Err(Error::UnexpectedChar { c: '&', column: 3 }),
+            "1 & 2".parse::<Expr>().map(|e| e.tokens)
+        );
+        assert_eq!(
+            Err(Error::UnexpectedEnd),
+            "1 + (2 *".parse::<Expr>().map(|e| e.tokens)
+        );
+        assert_eq!(
+            Err(Error::UnclosedParen(5)),
+            "1 + (2 * 3".parse::<Expr>().map(|e| e.tokens)
+        );
+        assert_eq!(
+            Err(Error::UnexpectedToken {
+                token: ")".to_string(),
+                column: 6
+            }),
+            "1 + 2) * 3".parse::<Expr>().map(|e| e.tokens)
+        );
+        assert_eq!(
+            Err(Error::UnknownOperator(Op::Mul)),
+            "1 * 2"
+                .parse::<Expr>()
+                .unwrap()
+                .parse(&PrecedenceTable::new().with(Op::Add, 1, Associativity::Left))
+        );
+    }
+
+    #[test]
+    fn test_precedence_table() {
+        let expr = "2 * 3 + 4 * 5".parse::<Expr>().unwrap();
+        assert_eq!(
+            Ok(50),
+            expr.evaluate::<i64>(&PrecedenceTable::left_to_right())
+        );
+        assert_eq!(Ok(26), expr.evaluate::<i64>(&PrecedenceTable::standard()));
+        assert_eq!(
+            Ok(70),
+            expr.evaluate::<i64>(&PrecedenceTable::addition_first())
+        );
+        let right_to_left = PrecedenceTable::new()
+            .with(Op::Add, 1, Associativity::Right)
+            .with(Op::Mul, 1, Associativity::Right);
+        assert_eq!(Ok(46), expr.evaluate::<i64>(&right_to_left));
+
+        let tightest = PrecedenceTable::new()
+            .with(Op::Sub, u32::MAX, Associativity::Left)
+            .with(Op::Mul, 1, Associativity::Left);
+        let expr = "10 - 4 - 3 * 2".parse::<Expr>().unwrap();
+        assert_eq!(Ok(6), expr.evaluate::<i64>(&tightest));
+    }
+
+    #[test]
+    fn test_pretty() {
+        let standard = PrecedenceTable::standard();
+        let expr = "((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2"
+            .parse::<Expr>()
+            .unwrap();
+        let ast = expr.parse(&PrecedenceTable::left_to_right()).unwrap();
+        let pretty = ast.pretty(&standard);
+        assert_eq!("((2 + 4) * 9 * ((6 + 9) * 8 + 6) + 6 + 2 + 4) * 2", pretty);
+        let reparsed = pretty.parse::<Expr>().unwrap().parse(&standard).unwrap();
+        assert_eq!(ast, reparsed);
+        assert_eq!(Ok(13632), reparsed.evaluate::<i64>());
+        assert_eq!(
+            "1 + (2 + 3)",
+            "1 + (2 + 3)"
+                .parse::<Expr>()
+                .unwrap()
+                .parse(&standard)
+                .unwrap()
+                .pretty(&standard)
+        );
+    }
+
+    #[test]
+    fn test_signed_arithmetic() {
+        let standard = PrecedenceTable::standard();
+        let eval = |s: &str| s.parse::<Expr>().unwrap().evaluate::<i64>(&standard);
+        assert_eq!(Ok(-7), eval("1 - 2 * 4"));
+        assert_eq!(Ok(3), eval("-7 / -2"));
+        assert_eq!(Ok(-1), eval("-7 % 2"));
+        assert_eq!(Ok(-512), eval("-2 ^ 3 ^ 2"));
+        assert_eq!(Ok(64), eval("(-2) ^ 6"));
+        assert_eq!(Ok(5), eval("2 - -3"));
+        assert_eq!(Ok(-4), eval("-(1 + 3)"));
+
+        assert_eq!(Err(Error::DivisionByZero), eval("1 / (2 - 2)"));
+        assert_eq!(Err(Error::DivisionByZero), eval("1 % 0"));
+        assert_eq!(Err(Error::Overflow(Op::Pow)), eval("2 ^ 63"));
+        assert_eq!(
+            Err(Error::Overflow(Op::Sub)),
+            eval("-9223372036854775807 - 2")
+        );
+        assert_eq!(
+            Err(Error::InvalidExponent("-1".to_string())),
+            eval("2 ^ -1")
+        );
+        assert_eq!(
+            Err(Error::LiteralOutOfRange("9223372036854775808".to_string())),
+            eval("9223372036854775808")
+        );
+        assert_eq!(Ok(i64::MIN), eval("-9223372036854775808"));
+        assert_eq!(Ok(i64::MIN), eval("-9223372036854775807 - 1"));
+        assert_eq!(
+            Err(Error::LiteralOutOfRange("-9223372036854775809".to_string())),
+            eval("-9223372036854775809")
+        );
+        assert_eq!(
+            Err(Error::NegationOverflow(i64::MIN.to_string())),
+            eval("-(-9223372036854775808)")
+        );
+        assert_eq!(
+            Ok(1 << 63),
+            "2 ^ 63"
+                .parse::<Expr>()
+                .unwrap()
+                .evaluate::<i128>(&standard)
+        );
+    }
+
+    #[test]
+    fn test_big_arithmetic() {
+        let standard = PrecedenceTable::standard();
+        let eval = |s: &str| {
+            s.parse::<Expr>()
+                .unwrap()
+                .evaluate::<BigInt>(&standard)
+                .map(|n| n.to_string())
+        };
+        assert_eq!(
+            Ok("340282366920938463463374607431768211456".to_string()),
+            eval("2 ^ 128")
+        );
+        assert_eq!(
+            Ok("-170141183460469231731687303715884105729".to_string()),
+            eval("-2 ^ 127 - 1")
+        );
+        assert_eq!(
+            Ok("18446744073709551615".to_string()),
+            eval("(18446744073709551615 * 18446744073709551615) / 18446744073709551615")
+        );
+        for (input, want) in [("-7 / 2", "-3"), ("-7 % 2", "-1"), ("-(-8)", "8")].iter() {
+            assert_eq!(Ok(want.to_string()), eval(input));
+        }
+        assert_eq!(Err(Error::DivisionByZero), eval("1 / (2 - 2)"));
+        assert_eq!(Err(Error::DivisionByZero), eval("1 % 0"));
+        assert_eq!(Err(Error::Overflow(Op::Pow)), eval("10 ^ 4000000000"));
+        assert_eq!(
+            Err(Error::InvalidExponent("-1".to_string())),
+            eval("2 ^ -1")
+        );
+        assert_eq!(
+            Err(Error::InvalidExponent("4294967296".to_string())),
+            eval("2 ^ 4294967296")
+        );
+        // Results outside u64 are fine as long as the final one fits.
+        let expr = "18446744073709551615 * 2 / 2".parse::<Expr>().unwrap();
+        assert_eq!(u64::MAX, expr.result());
+    }
+
+    #[test]
+    fn test_pretty_unary() {
+        let standard = PrecedenceTable::standard();
+        for (input, want) in [
+            ("-2 ^ 2", "-2 ^ 2"),
+            ("(-2) ^ 2", "(-2) ^ 2"),
+            ("-(1 - 2) * 3", "-(1 - 2) * 3"),
+            ("1 - (2 - 3)", "1 - (2 - 3)"),
+            ("2 ^ (3 ^ 2)", "2 ^ 3 ^ 2"),
+            ("(2 ^ 3) ^ 2", "(2 ^ 3) ^ 2"),
+            ("4 / -(-2)", "4 / -(-2)"),
+        ]
+        .iter()
+        {
+            let ast = input.parse::<Expr>().unwrap().parse(&standard).unwrap();
+            assert_eq!(*want, ast.pretty(&standard));
+            let reparsed = want.parse::<Expr>().unwrap().parse(&standard).unwrap();
+            assert_eq!(ast, reparsed);
+        }
+    }
 }
@@ -1,6 +1,10 @@
 use std::collections::HashMap;
 use thiserror::Error as ThisError;
 
+pub mod analysis;
+pub mod convert;
+pub mod parser;
+
 #[derive(ThisError, Debug)]
 pub enum Error {
     #[error("failed to parse rule number")]
@@ -17,57 +21,99 @@ pub enum Error {
 
     #[error("failed to parse fields in rule: {0}")]
     RuleParseFieldsError(String),
+
+    #[error("rule {0} is used but never defined")]
+    UndefinedRule(RuleNum),
+
+    #[error("invalid rule name: {0}")]
+    InvalidRuleName(String),
+
+    #[error("rule number {0} is already used for a named rule")]
+    RuleNumberTaken(RuleNum),
+
+    #[error("rule {0} recurses in a way a regular expression can't express")]
+    NotRegular(RuleNum),
+
+    #[error("failed to build regex")]
+    RegexError {
+        #[from]
+        source: regex::Error,
+    },
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
 type RuleNum = usize;
 
+// Rules are written either as `0: 4 1 5` with numbered rules or as
+// `greeting = hello, name;` in EBNF style, and may use names instead of
+// numbers in either form. Named rules are numbered after every numbered one.
 #[derive(Debug)]
-pub struct RuleMap(HashMap<RuleNum, Rule>);
+pub struct RuleMap {
+    rules: HashMap<RuleNum, Rule>,
+    names: HashMap<String, RuleNum>,
+    // Rule 0, unless the first rule defined has a name.
+    start: RuleNum,
+    next_id: RuleNum,
+}
 
 #[derive(Debug)]
 struct AlternativeList(Vec<RuleNum>);
 
 #[derive(Debug)]
 enum Rule {
-    Terminal(char),
+    Terminal(String),
     Nonterminal(Vec<AlternativeList>),
 }
 
-impl std::str::FromStr for AlternativeList {
-    type Err = Error;
-
-    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        Ok(AlternativeList(
-            s.trim()
-                .split(' ')
-                .map(|s| s.parse::<RuleNum>())
-                .collect::<std::result::Result<_, _>>()?,
-        ))
+// Splits a rule definition line into its name and body, in either syntax.
+fn split_definition(line: &str) -> Result<(&str, &str, bool)> {
+    let line = line.trim();
+    // Whichever separator comes first, as terminals may contain either.
+    match (line.find(": "), line.find(" = ")) {
+        (Some(i), j) if j.map_or(true, |j| i < j) => Ok((line[..i].trim(), &line[i + 2..], false)),
+        (_, Some(j)) => {
+            let body = &line[j + 3..];
+            Ok((
+                line[..j].trim(),
+                body.strip_suffix(';').unwrap_or(body),
+                true,
+            ))
+        }
+        _ => Err(Error::RuleParseFieldsError(line.to_string())),
     }
 }
 
-impl std::str::FromStr for Rule {
-    type Err = Error;
-
-    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        let s = s.trim();
-        match s.chars().next() {
-            Some('"') => Ok(Rule::Terminal(
-                s.chars()
-                    .nth(1)
-                    .ok_or_else(|| Error::TerminalParseError(s.to_string()))?,
-            )),
-            Some(_) => {
-                let alternatives = s
-                    .split(" | ")
-                    .map(|alt_str| alt_str.parse::<AlternativeList>())
-                    .collect::<std::result::Result<Vec<_>, _>>()?;
-                Ok(Rule::Nonterminal(alternatives))
+// Splits `s` wherever `split` is true outside of quoted terminals.
+fn split_unquoted(s: &str, split: impl Fn(char) -> bool) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quote = None;
+    let mut from = 0;
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if split(c) => {
+                parts.push(&s[from..i]);
+                from = i + c.len_utf8();
             }
-            None => Err(Error::EmptyRuleError),
+            None => {}
+        }
+    }
+    parts.push(&s[from..]);
+    parts
+}
+
+fn parse_terminal(s: &str) -> Result<String> {
+    let quote = s.chars().next();
+    match (quote, s.chars().last()) {
+        (Some(q @ '"'), Some(end)) | (Some(q @ '\''), Some(end))
+            if end == q && s.len() > 2 && !s[1..s.len() - 1].contains(q) =>
+        {
+            Ok(s[1..s.len() - 1].to_string())
         }
+        _ => Err(Error::TerminalParseError(s.to_string())),
     }
 }
 
@@ -75,51 +121,184 @@ impl std::str::FromStr for RuleMap {
     type Err = Error;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        Ok(RuleMap(
-            s.trim()
-                .lines()
-                .map(|line| {
-                    let fields = line.split(": ").collect::<Vec<_>>();
-                    if fields.len() != 2 {
-                        return Err(Error::RuleParseFieldsError(line.to_string()));
-                    }
-                    let rule_num = fields.get(0).unwrap().parse::<RuleNum>()?;
-                    let rule = fields.get(1).unwrap().parse::<Rule>()?;
-                    Ok((rule_num, rule))
-                })
-                .collect::<std::result::Result<HashMap<_, _>, _>>()?,
-        ))
+        let lines = s.trim().lines().filter(|l| !l.trim().is_empty());
+        let definitions = lines.map(split_definition).collect::<Result<Vec<_>>>()?;
+        // Names are numbered after every number mentioned anywhere.
+        let next_id = definitions
+            .iter()
+            .flat_map(|(name, body, ebnf)| {
+                let separators: &[char] = if *ebnf { &[' ', ',', '|'] } else { &[' ', '|'] };
+                body.split(separators).chain(Some(*name))
+            })
+            .filter_map(|token| token.parse::<RuleNum>().ok())
+            .max()
+            .map_or(0, |n| n + 1);
+        let mut rule_map = RuleMap {
+            rules: HashMap::new(),
+            names: HashMap::new(),
+            start: 0,
+            next_id,
+        };
+        for (i, (name, body, ebnf)) in definitions.into_iter().enumerate() {
+            let rule_num = rule_map.resolve(name)?;
+            let rule = rule_map.parse_rule(body, ebnf)?;
+            rule_map.rules.insert(rule_num, rule);
+            if i == 0 && name.parse::<RuleNum>().is_err() {
+                rule_map.start = rule_num;
+            }
+        }
+        Ok(rule_map)
     }
 }
 
 impl RuleMap {
+    // The rule number for a numbered or named rule reference, numbering new
+    // names as they appear.
+    fn resolve(&mut self, token: &str) -> Result<RuleNum> {
+        if let Ok(n) = token.parse::<RuleNum>() {
+            if self.names.values().any(|&m| m == n) {
+                return Err(Error::RuleNumberTaken(n));
+            }
+            self.next_id = self.next_id.max(n + 1);
+            return Ok(n);
+        }
+        let mut chars = token.chars();
+        let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid {
+            return Err(Error::InvalidRuleName(token.to_string()));
+        }
+        if let Some(&n) = self.names.get(token) {
+            return Ok(n);
+        }
+        let n = self.next_id;
+        self.next_id += 1;
+        self.names.insert(token.to_string(), n);
+        Ok(n)
+    }
+
+    // A rule number for a symbol in a rule body. Terminals used alongside
+    // other symbols get a rule of their own.
+    fn symbol(&mut self, token: &str) -> Result<RuleNum> {
+        if !token.starts_with(&['"', '\''][..]) {
+            return self.resolve(token);
+        }
+        let n = self.next_id;
+        self.next_id += 1;
+        self.rules.insert(n, Rule::Terminal(parse_terminal(token)?));
+        Ok(n)
+    }
+
+    // Parses either `4 1 5 | 2` or, in EBNF style, `a, b, "c" | d`.
+    fn parse_rule(&mut self, s: &str, ebnf: bool) -> Result<Rule> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(Error::EmptyRuleError);
+        }
+        if let Ok(t) = parse_terminal(s) {
+            return Ok(Rule::Terminal(t));
+        }
+        let mut alternatives = Vec::new();
+        for alt in split_unquoted(s, |c| c == '|') {
+            let symbols = match ebnf {
+                true => split_unquoted(alt, |c| c == ',')
+                    .into_iter()
+                    .map(str::trim)
+                    .collect::<Vec<_>>(),
+                false => split_unquoted(alt, char::is_whitespace)
+                    .into_iter()
+                    .filter(|t| !t.is_empty())
+                    .collect(),
+            };
+            if symbols.is_empty() || symbols.iter().any(|t| t.is_empty()) {
+                return Err(Error::RuleParseFieldsError(s.to_string()));
+            }
+            let list = symbols
+                .into_iter()
+                .map(|t| self.symbol(t))
+                .collect::<Result<_>>()?;
+            alternatives.push(AlternativeList(list));
+        }
+        Ok(Rule::Nonterminal(alternatives))
+    }
+
+    // A rule's name if it has one, otherwise its number.
+    pub fn name(&self, n: RuleNum) -> String {
+        self.names
+            .iter()
+            .find(|(_, &m)| m == n)
+            .map_or_else(|| n.to_string(), |(name, _)| name.clone())
+    }
+
+    pub fn start(&self) -> RuleNum {
+        self.start
+    }
+
+    // Fails on the lowest numbered rule that is used but never defined.
+    pub fn validate(&self) -> Result<()> {
+        match analysis::undefined(self).first() {
+            Some(&n) => Err(Error::UndefinedRule(n)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn analyze(&self) -> analysis::Report {
+        analysis::analyze(self)
+    }
+
+    // Builds a parser that matches messages against the start rule.
+    pub fn compile(&self) -> Result<parser::Parser> {
+        parser::Parser::new(self)
+    }
+
+    // A regex matching the same messages, if no rule recurses other than by
+    // repeating itself at either end.
+    pub fn to_regex(&self) -> Result<regex::Regex> {
+        convert::to_regex(self)
+    }
+
+    pub fn to_ebnf(&self) -> String {
+        convert::to_ebnf(self)
+    }
+
+    pub fn to_abnf(&self) -> String {
+        convert::to_abnf(self)
+    }
+
     pub fn rewrite(&mut self, rule_num: RuleNum, rule_str: &str) -> Result<()> {
-        let rule = rule_str.parse::<Rule>()?;
-        self.0.insert(rule_num, rule);
+        let rule = self.parse_rule(rule_str, false)?;
+        self.rules.insert(rule_num, rule);
         Ok(())
     }
 
     pub fn matches(&self, s: &str) -> bool {
         // Keep a list of AlternativeLists, as well as an iter to the string it needs to match
-        let mut alternatives = vec![(AlternativeList(vec![0]), s.chars())];
+        let mut alternatives = vec![(AlternativeList(vec![self.start]), s.chars())];
         // While list of AlternativeLists is not empty
         while !alternatives.is_empty() {
             // Pop an AlternativeList and string iter
             let (alternative, mut chars) = alternatives.pop().unwrap();
             // If rule list is empty
             if alternative.0.is_empty() {
-                // Success iff no more chars as well
-                return chars.next().is_none();
+                // Success iff no more chars as well, otherwise try the rest
+                if chars.next().is_none() {
+                    return true;
+                }
+                continue;
             }
             // For each rule in alternative list
             let rule_num = alternative.0.get(0).unwrap();
-            let rule = self.0.get(rule_num).unwrap();
+            // Undefined rules match nothing.
+            let rule = match self.rules.get(rule_num) {
+                Some(rule) => rule,
+                None => continue,
+            };
             match rule {
                 // If terminal:
-                Rule::Terminal(c) => {
-                    match chars.next() {
-                        // If matches next char in string
-                        Some(t) if t == *c => {
+                Rule::Terminal(t) => {
+                    match t.chars().all(|c| chars.next() == Some(c)) {
+                        // If matches the next chars in string
+                        true => {
                             // New AlternativeList with first elem missing
                             let new_list = AlternativeList(alternative.0[1..].to_vec());
                             // Push new list, iter
@@ -157,14 +336,14 @@ impl RuleMap {
 mod tests {
     use super::*;
 
-    static TEST_RULES: &str = &r#"0: 4 1 5
+    pub(super) static TEST_RULES: &str = &r#"0: 4 1 5
 1: 2 3 | 3 2
 2: 4 4 | 5 5
 3: 4 5 | 5 4
 4: "a"
 5: "b""#;
 
-    static TEST_RULES_2: &str = &r#"42: 9 14 | 10 1
+    pub(super) static TEST_RULES_2: &str = &r#"42: 9 14 | 10 1
 9: 14 27 | 1 26
 10: 23 14 | 28 1
 1: "a"
@@ -243,4 +422,16 @@ mod tests {
             test_strs.iter().filter(|&&s| rule_map.matches(s)).count()
         );
     }
+
+    #[test]
+    fn test_rule_map_matches_leftover_input() {
+        // The shorter alternative is tried first and runs out of rules with
+        // input left over; the longer one must still get a chance.
+        let rule_map = "0: 1 | 1 1\n1: \"a\"".parse::<RuleMap>().unwrap();
+        assert!(rule_map.matches("a"));
+        assert!(rule_map.matches("aa"));
+        assert!(!rule_map.matches("aaa"));
+        let rule_map = "0: 1 | 1 0\n1: \"a\"".parse::<RuleMap>().unwrap();
+        assert!(rule_map.matches("aaaa"));
+    }
 }
@@ -0,0 +1,343 @@
+use super::AlternativeList;
+use super::Error;
+use super::Result;
+use super::Rule;
+use super::RuleMap;
+use super::RuleNum;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+// What a rule map accepts, starting from its start rule. Rule lists are sorted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    // Used (or, for the start rule, needed) but never defined.
+    pub undefined: Vec<RuleNum>,
+    // Defined but not used by the start rule or anything it uses.
+    pub unreachable: Vec<RuleNum>,
+    // Defined but unable to match any string, e.g. because every
+    // alternative refers to an undefined rule or recurses forever.
+    pub unproductive: Vec<RuleNum>,
+    // Able to start with themselves, directly or through other rules.
+    pub left_recursive: Vec<RuleNum>,
+    pub finite: bool,
+    // Both are `None` if the start rule matches nothing; `max_len` is also `None` if
+    // the language is infinite.
+    pub min_len: Option<usize>,
+    pub max_len: Option<usize>,
+}
+
+fn alternatives(rules: &RuleMap, n: RuleNum) -> &[AlternativeList] {
+    match rules.rules.get(&n) {
+        Some(Rule::Nonterminal(alternatives)) => alternatives,
+        _ => &[],
+    }
+}
+
+fn sorted(rules: impl IntoIterator<Item = RuleNum>) -> Vec<RuleNum> {
+    rules
+        .into_iter()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+pub fn undefined(rules: &RuleMap) -> Vec<RuleNum> {
+    let used = rules
+        .rules
+        .keys()
+        .flat_map(|&n| alternatives(rules, n).iter().flat_map(|alt| alt.0.iter()))
+        .copied()
+        .chain(Some(rules.start));
+    sorted(used.filter(|n| !rules.rules.contains_key(n)))
+}
+
+// Rules reachable from `start` along `edges`.
+fn reachable(start: RuleNum, edges: &HashMap<RuleNum, Vec<RuleNum>>) -> HashSet<RuleNum> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![start];
+    while let Some(n) = stack.pop() {
+        if seen.insert(n) {
+            stack.extend(edges.get(&n).into_iter().flatten());
+        }
+    }
+    seen
+}
+
+// Rules that lie on a cycle of `edges`.
+fn cyclic(edges: &HashMap<RuleNum, Vec<RuleNum>>) -> Vec<RuleNum> {
+    sorted(edges.keys().copied().filter(|&n| {
+        let mut seen = HashSet::new();
+        let mut stack = edges[&n].clone();
+        while let Some(m) = stack.pop() {
+            if m == n {
+                return true;
+            }
+            if seen.insert(m) {
+                stack.extend(edges.get(&m).into_iter().flatten());
+            }
+        }
+        false
+    }))
+}
+
+// Length of the shortest string each productive rule matches.
+fn min_lengths(rules: &RuleMap) -> HashMap<RuleNum, usize> {
+    let mut min = HashMap::new();
+    loop {
+        let mut changed = false;
+        for (&n, rule) in rules.rules.iter() {
+            let len = match rule {
+                Rule::Terminal(t) => Some(t.chars().count()),
+                Rule::Nonterminal(alternatives) => alternatives
+                    .iter()
+                    .filter_map(|alt| alt.0.iter().map(|r| min.get(r).copied()).sum())
+                    .min(),
+            };
+            if let Some(len) = len {
+                if min.get(&n).map_or(true, |&m| len < m) {
+                    min.insert(n, len);
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return min;
+        }
+    }
+}
+
+// Length of the longest string each rule in `live` matches, using only the
+// alternatives in `edges`. The rules must not recurse through an alternative
+// of two or more rules, or this never settles; unit cycles add no length.
+fn max_lengths(
+    rules: &RuleMap,
+    edges: &HashMap<RuleNum, Vec<Vec<RuleNum>>>,
+    live: &HashSet<RuleNum>,
+) -> HashMap<RuleNum, usize> {
+    let mut max = live
+        .iter()
+        .filter_map(|n| match rules.rules.get(n) {
+            Some(Rule::Terminal(t)) => Some((*n, t.chars().count())),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+    loop {
+        let mut changed = false;
+        for n in live.iter().filter(|n| edges.contains_key(n)) {
+            let len = edges[n]
+                .iter()
+                .filter_map(|alt| alt.iter().map(|r| max.get(r).copied()).sum())
+                .max();
+            if let Some(len) = len {
+                if max.get(n).map_or(true, |&m| len > m) {
+                    max.insert(*n, len);
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return max;
+        }
+    }
+}
+
+pub fn analyze(rules: &RuleMap) -> Report {
+    let uses = rules
+        .rules
+        .keys()
+        .map(|&n| {
+            let used = alternatives(rules, n).iter().flat_map(|alt| alt.0.clone());
+            (n, used.collect())
+        })
+        .collect();
+    let reached = reachable(rules.start, &uses);
+    let min = min_lengths(rules);
+
+    // Rules never derive the empty string, so only the first rule of each
+    // alternative can start a match.
+    let starts = rules
+        .rules
+        .keys()
+        .map(|&n| {
+            let firsts = alternatives(rules, n)
+                .iter()
+                .filter_map(|alt| alt.0.first());
+            (n, firsts.copied().collect())
+        })
+        .collect();
+
+    // Nonterminals with only their productive alternatives. The language is
+    // infinite iff these recurse somewhere reachable from the start rule
+    // through an alternative of two or more rules; since no rule matches the
+    // empty string, only those make matches longer.
+    let productive = rules
+        .rules
+        .keys()
+        .filter(|&n| min.contains_key(n) && matches!(rules.rules[n], Rule::Nonterminal(_)))
+        .map(|&n| {
+            let alternatives = alternatives(rules, n)
+                .iter()
+                .filter(|alt| alt.0.iter().all(|r| min.contains_key(r)))
+                .map(|alt| alt.0.clone())
+                .collect::<Vec<_>>();
+            (n, alternatives)
+        })
+        .collect::<HashMap<_, _>>();
+    let productive_uses = productive
+        .iter()
+        .map(|(&n, alts)| (n, alts.iter().flatten().copied().collect()))
+        .collect::<HashMap<_, Vec<_>>>();
+    let live = reachable(rules.start, &productive_uses);
+    let finite = !min.contains_key(&rules.start)
+        || !live.iter().filter(|n| productive.contains_key(n)).any(|n| {
+            productive[n]
+                .iter()
+                .filter(|alt| alt.len() > 1)
+                .flatten()
+                .any(|&r| reachable(r, &productive_uses).contains(n))
+        });
+
+    Report {
+        undefined: undefined(rules),
+        unreachable: sorted(rules.rules.keys().copied().filter(|n| !reached.contains(n))),
+        unproductive: sorted(rules.rules.keys().copied().filter(|n| !min.contains_key(n))),
+        left_recursive: cyclic(&starts),
+        finite,
+        min_len: min.get(&rules.start).copied(),
+        max_len: match (finite, min.contains_key(&rules.start)) {
+            (true, true) => max_lengths(rules, &productive, &live)
+                .get(&rules.start)
+                .copied(),
+            _ => None,
+        },
+    }
+}
+
+// Every string of at most `max_len` characters that the start rule matches, shortest
+// first.
+pub fn enumerate(rules: &RuleMap, max_len: usize) -> Result<Vec<String>> {
+    if let Some(&n) = undefined(rules).first() {
+        return Err(Error::UndefinedRule(n));
+    }
+    let mut matched: HashMap<RuleNum, BTreeSet<String>> = HashMap::new();
+    // Each pass can only add strings, and there are finitely many short
+    // enough ones, so this settles.
+    loop {
+        let mut changed = false;
+        for (&n, rule) in rules.rules.iter() {
+            let strings = match rule {
+                Rule::Terminal(t) => Some(t.clone())
+                    .filter(|t| t.chars().count() <= max_len)
+                    .into_iter()
+                    .collect(),
+                Rule::Nonterminal(alternatives) => alternatives
+                    .iter()
+                    .flat_map(|alt| {
+                        alt.0.iter().fold(vec![String::new()], |prefixes, r| {
+                            let empty = BTreeSet::new();
+                            let suffixes = matched.get(r).unwrap_or(&empty);
+                            prefixes
+                                .iter()
+                                .flat_map(|p| suffixes.iter().map(move |s| p.clone() + s))
+                                .filter(|s| s.chars().count() <= max_len)
+                                .collect()
+                        })
+                    })
+                    .collect::<BTreeSet<_>>(),
+            };
+            if strings.len() > matched.get(&n).map_or(0, BTreeSet::len) {
+                matched.insert(n, strings);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    let mut strings = matched
+        .remove(&rules.start)
+        .unwrap_or_default()
+        .into_iter()
+        .collect::<Vec<_>>();
+    strings.sort_by_key(|s| s.chars().count());
+    Ok(strings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::TEST_RULES;
+    use super::super::tests::TEST_RULES_2;
+    use super::*;
+
+    #[test]
+    fn test_analyze() {
+        let report = TEST_RULES.parse::<RuleMap>().unwrap().analyze();
+        assert!(report.finite);
+        assert_eq!((Some(6), Some(6)), (report.min_len, report.max_len));
+        assert!(report.undefined.is_empty() && report.unreachable.is_empty());
+        assert!(report.left_recursive.is_empty());
+
+        let mut rule_map = TEST_RULES_2.parse::<RuleMap>().unwrap();
+        let report = rule_map.analyze();
+        assert!(report.finite);
+        assert_eq!((Some(15), Some(15)), (report.min_len, report.max_len));
+        rule_map.rewrite(8, "42 | 42 8").unwrap();
+        rule_map.rewrite(11, "42 31 | 42 11 31").unwrap();
+        let report = rule_map.analyze();
+        assert!(!report.finite);
+        assert_eq!((Some(15), None), (report.min_len, report.max_len));
+        assert!(report.left_recursive.is_empty());
+
+        let rule_map = "0: 1\n1: 2 | 3\n2: 1\n3: \"a\"".parse::<RuleMap>().unwrap();
+        let report = rule_map.analyze();
+        assert!(report.finite);
+        assert_eq!((Some(1), Some(1)), (report.min_len, report.max_len));
+        assert_eq!(vec![1, 2], report.left_recursive);
+        let rule_map = "0: 1\n1: 2 | 3\n2: 1 3\n3: \"a\""
+            .parse::<RuleMap>()
+            .unwrap();
+        let report = rule_map.analyze();
+        assert!(!report.finite);
+        assert_eq!((Some(1), None), (report.min_len, report.max_len));
+    }
+
+    #[test]
+    fn test_analyze_broken() {
+        let rule_map = "0: 1 2 | 3\n1: 1 4 | 4\n4: \"a\"\n5: \"b\"\n6: 6 5"
+            .parse::<RuleMap>()
+            .unwrap();
+        assert_eq!(
+            Report {
+                undefined: vec![2, 3],
+                unreachable: vec![5, 6],
+                unproductive: vec![0, 6],
+                left_recursive: vec![1, 6],
+                finite: true,
+                min_len: None,
+                max_len: None,
+            },
+            rule_map.analyze()
+        );
+        assert!(matches!(rule_map.validate(), Err(Error::UndefinedRule(2))));
+        let rule_map = "0: 1 2\n1: \"a\"".parse::<RuleMap>().unwrap();
+        assert!(!rule_map.matches("aa"));
+    }
+
+    #[test]
+    fn test_enumerate() {
+        let rule_map = TEST_RULES.parse::<RuleMap>().unwrap();
+        assert!(enumerate(&rule_map, 5).unwrap().is_empty());
+        let strings = enumerate(&rule_map, 6).unwrap();
+        assert_eq!(8, strings.len());
+        assert!(strings.iter().all(|s| rule_map.matches(s)));
+
+        let rule_map = "0: 1 | 1 0\n1: \"a\" ".parse::<RuleMap>().unwrap();
+        assert_eq!(vec!["a", "aa", "aaa"], enumerate(&rule_map, 3).unwrap());
+        let rule_map = "0: 1 2".parse::<RuleMap>().unwrap();
+        assert!(matches!(
+            enumerate(&rule_map, 3),
+            Err(Error::UndefinedRule(1))
+        ));
+    }
+}
@@ -0,0 +1,290 @@
+use super::Error;
+use super::Result;
+use super::Rule;
+use super::RuleMap;
+use super::RuleNum;
+use regex::Regex;
+use regex::RegexBuilder;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// Can never match, for rules that derive nothing.
+const NOTHING: &str = r"\b\B";
+
+// Expanded grammars get large; the day 19 rules need more than the default.
+const SIZE_LIMIT: usize = 1 << 26;
+
+struct RegexWriter<'a> {
+    rules: &'a RuleMap,
+    patterns: HashMap<RuleNum, String>,
+    // Rules being expanded, so that recursion through other rules is caught.
+    stack: Vec<RuleNum>,
+}
+
+impl RegexWriter<'_> {
+    fn pattern(&mut self, n: RuleNum) -> Result<String> {
+        if let Some(pattern) = self.patterns.get(&n) {
+            return Ok(pattern.clone());
+        }
+        if self.stack.contains(&n) {
+            return Err(Error::NotRegular(n));
+        }
+        let alternatives = match self.rules.rules.get(&n) {
+            Some(Rule::Terminal(t)) => return Ok(regex::escape(t)),
+            Some(Rule::Nonterminal(alternatives)) => alternatives,
+            None => return Err(Error::UndefinedRule(n)),
+        };
+        self.stack.push(n);
+        // A rule like `n: a | b n | n c` matches b*(a)c*.
+        let (mut base, mut prefixes, mut suffixes) = (Vec::new(), Vec::new(), Vec::new());
+        for alt in alternatives {
+            let list = &alt.0;
+            let uses = list.iter().filter(|&&r| r == n).count();
+            let (rest, kind) = match (list.first(), list.last()) {
+                _ if uses == 0 => (&list[..], &mut base),
+                (_, Some(&last)) if uses == 1 && last == n => {
+                    (&list[..list.len() - 1], &mut prefixes)
+                }
+                (Some(&first), _) if uses == 1 && first == n => (&list[1..], &mut suffixes),
+                _ => return Err(Error::NotRegular(n)),
+            };
+            let mut pattern = String::new();
+            for &r in rest {
+                pattern.push_str(&self.pattern(r)?);
+            }
+            kind.push(pattern);
+        }
+        self.stack.pop();
+
+        let group = |patterns: &[String]| format!("(?:{})", patterns.join("|"));
+        let mut pattern = String::new();
+        if !prefixes.is_empty() {
+            write!(pattern, "{}*", group(&prefixes)).unwrap();
+        }
+        match base.is_empty() {
+            true => pattern.push_str(NOTHING),
+            false => pattern.push_str(&group(&base)),
+        }
+        if !suffixes.is_empty() {
+            write!(pattern, "{}*", group(&suffixes)).unwrap();
+        }
+        self.patterns.insert(n, pattern.clone());
+        Ok(pattern)
+    }
+}
+
+pub fn to_regex(rules: &RuleMap) -> Result<Regex> {
+    let mut writer = RegexWriter {
+        rules,
+        patterns: HashMap::new(),
+        stack: Vec::new(),
+    };
+    let pattern = writer.pattern(rules.start)?;
+    Ok(RegexBuilder::new(&format!("^{}$", pattern))
+        .size_limit(SIZE_LIMIT)
+        .build()?)
+}
+
+// Numbered rules become `r4`, as grammar notations need names to start with
+// a letter. If a named rule already looks like that, ignoring case as ABNF
+// does, the prefix grows to `rr` and so on.
+fn numbered_prefix(rules: &RuleMap) -> String {
+    let mut prefix = "r".to_string();
+    while rules.names.keys().any(|name| {
+        name.to_ascii_lowercase()
+            .strip_prefix(&prefix)
+            .is_some_and(|rest| !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit()))
+    }) {
+        prefix.push('r');
+    }
+    prefix
+}
+
+fn ident(rules: &RuleMap, prefix: &str, n: RuleNum) -> String {
+    match rules.names.iter().find(|(_, &m)| m == n) {
+        Some((name, _)) => name.clone(),
+        None => format!("{}{}", prefix, n),
+    }
+}
+
+// The start rule first, then the rest in the order they were numbered.
+fn ordered(rules: &RuleMap) -> Vec<RuleNum> {
+    let mut numbers = rules.rules.keys().copied().collect::<Vec<_>>();
+    numbers.sort_unstable_by_key(|&n| (n != rules.start, n));
+    numbers
+}
+
+// `rename` adapts every rule name, both where it is defined and where it is
+// used, to the notation.
+fn write_grammar(
+    rules: &RuleMap,
+    rename: impl Fn(String) -> String,
+    line: impl Fn(&str, &str) -> String,
+    terminal: impl Fn(&str) -> String,
+    separator: &str,
+    alternation: &str,
+) -> String {
+    let prefix = numbered_prefix(rules);
+    let ident = |n| rename(ident(rules, &prefix, n));
+    let mut out = String::new();
+    for n in ordered(rules) {
+        let body = match &rules.rules[&n] {
+            Rule::Terminal(t) => terminal(t),
+            Rule::Nonterminal(alternatives) => alternatives
+                .iter()
+                .map(|alt| alt.0.iter().map(|&r| ident(r)).collect::<Vec<_>>())
+                .map(|symbols| symbols.join(separator))
+                .collect::<Vec<_>>()
+                .join(alternation),
+        };
+        out.push_str(&line(&ident(n), &body));
+    }
+    out
+}
+
+// ISO EBNF, which `RuleMap` can also parse back.
+pub fn to_ebnf(rules: &RuleMap) -> String {
+    write_grammar(
+        rules,
+        |name| name,
+        |name, body| format!("{} = {};\n", name, body),
+        |t| match t.contains('"') {
+            true => format!("'{}'", t),
+            false => format!("\"{}\"", t),
+        },
+        ", ",
+        " | ",
+    )
+}
+
+// RFC 5234 ABNF, with RFC 7405 case-sensitive strings where the terminal can
+// be quoted and hex values otherwise.
+pub fn to_abnf(rules: &RuleMap) -> String {
+    write_grammar(
+        rules,
+        |name| name.replace('_', "-"),
+        |name, body| format!("{} = {}\n", name, body),
+        |t| match t
+            .chars()
+            .all(|c| c == ' ' || c.is_ascii_graphic() && c != '"')
+        {
+            true => format!("%s\"{}\"", t),
+            false => {
+                let hex = t.chars().map(|c| format!("{:X}", c as u32));
+                format!("%x{}", hex.collect::<Vec<_>>().join("."))
+            }
+        },
+        " ",
+        " / ",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::TEST_RULES;
+    use super::super::tests::TEST_RULES_2;
+    use super::*;
+
+    fn all_strings(len: usize) -> Vec<String> {
+        (0..1 << len)
+            .map(|bits: usize| {
+                (0..len)
+                    .map(|i| if bits >> i & 1 == 0 { 'a' } else { 'b' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_to_regex() {
+        let rule_map = TEST_RULES.parse::<RuleMap>().unwrap();
+        let regex = rule_map.to_regex().unwrap();
+        let parser = rule_map.compile().unwrap();
+        for s in (5..=7).flat_map(all_strings) {
+            assert_eq!(parser.matches(&s), regex.is_match(&s), "{}", s);
+        }
+
+        let mut rule_map = TEST_RULES_2.parse::<RuleMap>().unwrap();
+        rule_map.rewrite(8, "42 | 42 8").unwrap();
+        let parser = rule_map.compile().unwrap();
+        let regex = rule_map.to_regex().unwrap();
+        for s in ["bbabbbbaabaabba", "ababaaaaaabaaab", "aaaabbaaaabbaaa"].iter() {
+            assert_eq!(parser.matches(s), regex.is_match(s));
+        }
+        rule_map.rewrite(11, "42 31 | 42 11 31").unwrap();
+        assert!(matches!(rule_map.to_regex(), Err(Error::NotRegular(11))));
+
+        let rule_map = "0: 1 0 | 2 | 0 3\n1: \"c\"\n2: \"a\"\n3: \"b\""
+            .parse::<RuleMap>()
+            .unwrap();
+        assert_eq!("^(?:c)*(?:a)(?:b)*$", rule_map.to_regex().unwrap().as_str());
+        let rule_map = "0: 1 2\n1: \"a\"".parse::<RuleMap>().unwrap();
+        assert!(matches!(rule_map.to_regex(), Err(Error::UndefinedRule(2))));
+    }
+
+    #[test]
+    fn test_grammar_round_trip() {
+        let rule_map = TEST_RULES.parse::<RuleMap>().unwrap();
+        let ebnf = rule_map.to_ebnf();
+        assert!(ebnf.starts_with("r0 = r4, r1, r5;\nr1 = r2, r3 | r3, r2;\n"));
+        let parsed = ebnf.parse::<RuleMap>().unwrap();
+        for s in all_strings(6) {
+            assert_eq!(rule_map.matches(&s), parsed.matches(&s), "{}", s);
+        }
+        // Exported names are numbered in order of use when read back.
+        let exported = parsed.to_ebnf();
+        assert_eq!(exported, exported.parse::<RuleMap>().unwrap().to_ebnf());
+        assert!(rule_map
+            .to_abnf()
+            .contains("r1 = r2 r3 / r3 r2\nr2 = r4 r4 / r5 r5\n"));
+    }
+
+    #[test]
+    fn test_named_rules() {
+        let rule_map = r#"greeting = salute, space, who;
+space = " ";
+salute = "hello" | "hi";
+who = "world" | name | quote, name, quote;
+name: "sea monster" | "ferry"
+quote = '"';
+"#
+        .parse::<RuleMap>()
+        .unwrap();
+        assert_eq!("greeting", rule_map.name(rule_map.start()));
+        assert!(rule_map.matches("hello world"));
+        assert!(rule_map.matches("hi sea monster"));
+        assert!(!rule_map.matches("hi sea"));
+        let parser = rule_map.compile().unwrap();
+        assert!(parser.matches("hello ferry"));
+        assert_eq!("hi world", parser.parse("hi world").unwrap().text());
+        assert!(rule_map.to_regex().unwrap().is_match("hello sea monster"));
+        assert!(parser.matches("hi \"ferry\""));
+        let abnf = rule_map.to_abnf();
+        assert!(abnf.starts_with("greeting = salute space who\n"));
+        assert!(abnf.contains("space = %s\" \"\n") && abnf.contains("quote = %x22\n"));
+
+        // Names are spelled the same where rules are defined and used, and
+        // numbered rules don't take over a name like `r4`.
+        let clashing = "0: my_rule 4 | R4\nmy_rule: \"a\"\nR4: \"b\"\n4: \"c\""
+            .parse::<RuleMap>()
+            .unwrap();
+        let abnf = clashing.to_abnf();
+        assert!(abnf.starts_with("rr0 = my-rule rr4 / R4\n"));
+        assert!(abnf.contains("my-rule = %s\"a\"\n") && abnf.contains("rr4 = %s\"c\"\n"));
+        let parsed = clashing.to_ebnf().parse::<RuleMap>().unwrap();
+        for s in ["ac", "b", "c", "ab"].iter() {
+            assert_eq!(clashing.matches(s), parsed.matches(s), "{}", s);
+        }
+        // The shortest message is `hi world`.
+        assert_eq!(Some(8), rule_map.analyze().min_len);
+
+        assert!(matches!(
+            "0: 1\n1x: \"a\"".parse::<RuleMap>(),
+            Err(Error::InvalidRuleName(_))
+        ));
+        assert!(matches!(
+            "0: \"\"".parse::<RuleMap>(),
+            Err(Error::TerminalParseError(_))
+        ));
+    }
+}
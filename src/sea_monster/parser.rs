@@ -0,0 +1,504 @@
+use super::Error;
+use super::Result;
+use super::Rule;
+use super::RuleMap;
+use super::RuleNum;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+// An Earley parser for a `RuleMap`, so rules may recurse in any way,
+// including the loops introduced by `RuleMap::rewrite`.
+#[derive(Debug)]
+pub struct Parser {
+    numbers: Vec<RuleNum>,
+    rules: Vec<Compiled>,
+    // Production 0 derives the start rule, so that matching a message means
+    // completing it across the whole input.
+    productions: Vec<Production>,
+}
+
+#[derive(Debug)]
+enum Compiled {
+    Terminal(Vec<char>),
+    Productions(Vec<usize>),
+}
+
+// One alternative of a rule, with other rules referred to by index.
+#[derive(Debug)]
+struct Production {
+    lhs: usize,
+    alternative: usize,
+    rhs: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Item {
+    production: usize,
+    dot: usize,
+    origin: usize,
+}
+
+// Earley sets, one per position in the input, with the items in each set
+// that are waiting on a given rule.
+struct Chart {
+    sets: Vec<Vec<Item>>,
+    waiting: Vec<HashMap<usize, Vec<Item>>>,
+    // Items in the set being processed. Scanning advances distinct items, so
+    // later sets need no duplicate check until their turn comes.
+    current: usize,
+    seen: HashSet<Item>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tree {
+    Leaf {
+        rule: RuleNum,
+        text: String,
+    },
+    Node {
+        rule: RuleNum,
+        // Index of the alternative in the rule that was used.
+        alternative: usize,
+        children: Vec<Tree>,
+    },
+}
+
+// Recovers a derivation from a finished chart.
+struct Derivation<'a> {
+    parser: &'a Parser,
+    chars: &'a [char],
+    // Ends of each completed (rule, start) span.
+    ends: HashMap<(usize, usize), Vec<usize>>,
+    // Completed (production, start, end) spans.
+    complete: HashSet<(usize, usize, usize)>,
+    // (production, dot, position, end) tails known not to derive.
+    failed: HashSet<(usize, usize, usize, usize)>,
+    // (rule, start, end) spans being derived further up. Unit cycles can
+    // re-enter one without consuming input, which counts as a failure.
+    in_progress: HashSet<(usize, usize, usize)>,
+    // How many times a span was re-entered. Failures seen while this changes
+    // depend on what is in progress, so they aren't remembered.
+    cuts: usize,
+}
+
+impl Chart {
+    fn new() -> Chart {
+        Chart {
+            sets: Vec::new(),
+            waiting: Vec::new(),
+            current: 0,
+            seen: HashSet::new(),
+        }
+    }
+
+    fn start(&mut self, i: usize) {
+        self.current = i;
+        self.seen.clear();
+        self.seen.extend(self.sets[i].iter().copied());
+    }
+
+    fn add(&mut self, i: usize, item: Item) {
+        while self.sets.len() <= i {
+            self.sets.push(Vec::new());
+            self.waiting.push(HashMap::new());
+        }
+        if i != self.current || self.seen.insert(item) {
+            self.sets[i].push(item);
+        }
+    }
+}
+
+impl Item {
+    fn advance(self) -> Item {
+        Item {
+            dot: self.dot + 1,
+            ..self
+        }
+    }
+}
+
+impl Parser {
+    pub(super) fn new(map: &RuleMap) -> Result<Parser> {
+        let mut numbers = map.rules.keys().copied().collect::<Vec<_>>();
+        numbers.sort_unstable();
+        let index = numbers
+            .iter()
+            .enumerate()
+            .map(|(i, &n)| (n, i))
+            .collect::<HashMap<_, _>>();
+        let lookup = |n: &RuleNum| index.get(n).copied().ok_or(Error::UndefinedRule(*n));
+
+        let mut productions = vec![Production {
+            lhs: numbers.len(),
+            alternative: 0,
+            rhs: vec![lookup(&map.start)?],
+        }];
+        let mut rules = Vec::new();
+        for (i, n) in numbers.iter().enumerate() {
+            rules.push(match &map.rules[n] {
+                Rule::Terminal(t) => Compiled::Terminal(t.chars().collect()),
+                Rule::Nonterminal(alternatives) => {
+                    let mut ids = Vec::new();
+                    for (alternative, list) in alternatives.iter().enumerate() {
+                        ids.push(productions.len());
+                        productions.push(Production {
+                            lhs: i,
+                            alternative,
+                            rhs: list.0.iter().map(lookup).collect::<Result<_>>()?,
+                        });
+                    }
+                    Compiled::Productions(ids)
+                }
+            });
+        }
+        Ok(Parser {
+            numbers,
+            rules,
+            productions,
+        })
+    }
+
+    fn chart(&self, chars: &[char]) -> Chart {
+        let mut chart = Chart::new();
+        chart.add(
+            0,
+            Item {
+                production: 0,
+                dot: 0,
+                origin: 0,
+            },
+        );
+        // Stops early once no item could scan further.
+        let mut i = 0;
+        while i < chart.sets.len() {
+            chart.start(i);
+            let mut predicted = vec![false; self.rules.len()];
+            let mut j = 0;
+            while j < chart.sets[i].len() {
+                let item = chart.sets[i][j];
+                j += 1;
+                let production = &self.productions[item.production];
+                let next = match production.rhs.get(item.dot) {
+                    Some(&next) => next,
+                    None => {
+                        // Rules never derive the empty string, so the origin
+                        // set is always an earlier, finished one.
+                        let lhs = production.lhs;
+                        let count = chart.waiting[item.origin].get(&lhs).map_or(0, Vec::len);
+                        for k in 0..count {
+                            let waiting = chart.waiting[item.origin][&lhs][k];
+                            chart.add(i, waiting.advance());
+                        }
+                        continue;
+                    }
+                };
+                match &self.rules[next] {
+                    Compiled::Terminal(t) => {
+                        if chars[i..].starts_with(t) {
+                            chart.add(i + t.len(), item.advance());
+                        }
+                    }
+                    Compiled::Productions(ids) => {
+                        chart.waiting[i].entry(next).or_default().push(item);
+                        if std::mem::replace(&mut predicted[next], true) {
+                            continue;
+                        }
+                        for &production in ids {
+                            chart.add(
+                                i,
+                                Item {
+                                    production,
+                                    dot: 0,
+                                    origin: i,
+                                },
+                            );
+                        }
+                    }
+                }
+            }
+            i += 1;
+        }
+        chart
+    }
+
+    pub fn matches(&self, s: &str) -> bool {
+        let chars = s.chars().collect::<Vec<_>>();
+        let accept = Item {
+            production: 0,
+            dot: 1,
+            origin: 0,
+        };
+        self.chart(&chars)
+            .sets
+            .get(chars.len())
+            .is_some_and(|set| set.contains(&accept))
+    }
+
+    // A derivation of `s` from the start rule, if it matches. When there is
+    // more than one, earlier alternatives are preferred.
+    pub fn parse(&self, s: &str) -> Option<Tree> {
+        let chars = s.chars().collect::<Vec<_>>();
+        let chart = self.chart(&chars);
+        let mut derivation = Derivation {
+            parser: self,
+            chars: &chars,
+            ends: HashMap::new(),
+            complete: HashSet::new(),
+            failed: HashSet::new(),
+            in_progress: HashSet::new(),
+            cuts: 0,
+        };
+        for (end, set) in chart.sets.iter().enumerate() {
+            for item in set {
+                let production = &self.productions[item.production];
+                if item.dot == production.rhs.len() {
+                    derivation
+                        .ends
+                        .entry((production.lhs, item.origin))
+                        .or_default()
+                        .push(end);
+                    derivation
+                        .complete
+                        .insert((item.production, item.origin, end));
+                }
+            }
+        }
+        if chart.sets.len() <= chars.len() || !derivation.complete.contains(&(0, 0, chars.len())) {
+            return None;
+        }
+        derivation.node(self.productions[0].rhs[0], 0, chars.len())
+    }
+}
+
+impl Derivation<'_> {
+    fn node(&mut self, rule: usize, start: usize, end: usize) -> Option<Tree> {
+        let parser = self.parser;
+        match &parser.rules[rule] {
+            Compiled::Terminal(t) => match self.chars.get(start..end) {
+                Some(text) if text == &t[..] => Some(Tree::Leaf {
+                    rule: parser.numbers[rule],
+                    text: text.iter().collect(),
+                }),
+                _ => None,
+            },
+            Compiled::Productions(ids) => {
+                if !self.in_progress.insert((rule, start, end)) {
+                    self.cuts += 1;
+                    return None;
+                }
+                let mut tree = None;
+                for &p in ids {
+                    if !self.complete.contains(&(p, start, end)) {
+                        continue;
+                    }
+                    if let Some(children) = self.children(p, 0, start, end) {
+                        tree = Some(Tree::Node {
+                            rule: parser.numbers[rule],
+                            alternative: parser.productions[p].alternative,
+                            children,
+                        });
+                        break;
+                    }
+                }
+                self.in_progress.remove(&(rule, start, end));
+                tree
+            }
+        }
+    }
+
+    // Derivations for the symbols of `production` from `dot` onwards that
+    // cover exactly `pos..end`.
+    fn children(
+        &mut self,
+        production: usize,
+        dot: usize,
+        pos: usize,
+        end: usize,
+    ) -> Option<Vec<Tree>> {
+        let rhs = &self.parser.productions[production].rhs;
+        if dot == rhs.len() {
+            return if pos == end { Some(Vec::new()) } else { None };
+        }
+        if self.failed.contains(&(production, dot, pos, end)) {
+            return None;
+        }
+        let cuts = self.cuts;
+        let symbol = rhs[dot];
+        let ends = match &self.parser.rules[symbol] {
+            Compiled::Terminal(t) => vec![pos + t.len()],
+            Compiled::Productions(_) => self.ends.get(&(symbol, pos)).cloned().unwrap_or_default(),
+        };
+        for split in ends.into_iter().filter(|&e| e <= end) {
+            if let Some(mut rest) = self.children(production, dot + 1, split, end) {
+                if let Some(first) = self.node(symbol, pos, split) {
+                    rest.insert(0, first);
+                    return Some(rest);
+                }
+            }
+        }
+        if self.cuts == cuts {
+            self.failed.insert((production, dot, pos, end));
+        }
+        None
+    }
+}
+
+impl Tree {
+    pub fn rule(&self) -> RuleNum {
+        match self {
+            Tree::Leaf { rule, .. } | Tree::Node { rule, .. } => *rule,
+        }
+    }
+
+    pub fn children(&self) -> &[Tree] {
+        match self {
+            Tree::Leaf { .. } => &[],
+            Tree::Node { children, .. } => children,
+        }
+    }
+
+    // The part of the message this tree covers.
+    pub fn text(&self) -> String {
+        match self {
+            Tree::Leaf { text, .. } => text.clone(),
+            Tree::Node { children, .. } => children.iter().map(Tree::text).collect(),
+        }
+    }
+}
+
+// Leaves print as `4:a`, nodes as `0(...)` around their children.
+impl std::fmt::Display for Tree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Tree::Leaf { rule, text } => write!(f, "{}:{}", rule, text),
+            Tree::Node { rule, children, .. } => {
+                write!(f, "{}(", rule)?;
+                for (i, child) in children.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", child)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::TEST_RULES;
+    use super::super::tests::TEST_RULES_2;
+    use super::*;
+
+    #[test]
+    fn test_parse_tree() {
+        let parser = TEST_RULES.parse::<RuleMap>().unwrap().compile().unwrap();
+        let tree = parser.parse("ababbb").unwrap();
+        assert_eq!("ababbb", tree.text());
+        assert_eq!("0(4:a 1(3(5:b 4:a) 2(5:b 5:b)) 5:b)", tree.to_string());
+        assert_eq!(
+            vec![4, 1, 5],
+            tree.children().iter().map(Tree::rule).collect::<Vec<_>>()
+        );
+        assert_eq!(None, parser.parse("bababa"));
+        assert!(parser.matches("abbbab"));
+        assert!(!parser.matches("aaaabbb"));
+        assert!(!parser.matches(""));
+    }
+
+    #[test]
+    fn test_parse_looping() {
+        let mut rule_map = TEST_RULES_2.parse::<RuleMap>().unwrap();
+        rule_map.rewrite(8, "42 | 42 8").unwrap();
+        rule_map.rewrite(11, "42 31 | 42 11 31").unwrap();
+        let parser = rule_map.compile().unwrap();
+        let message = "babbbbaabbbbbabbbbbbaabaaabaaa";
+        let tree = parser.parse(message).unwrap();
+        assert_eq!(message, tree.text());
+        let (eight, eleven) = (&tree.children()[0], &tree.children()[1]);
+        assert_eq!((8, 11), (eight.rule(), eleven.rule()));
+        assert!(matches!(eleven, Tree::Node { alternative: 1, .. }));
+        assert!(!parser.matches("aaaabbaaaabbaaaa"));
+    }
+
+    #[test]
+    fn test_parse_unit_cycle() {
+        let parser = "0: 1\n1: 0 | 2\n2: \"a\""
+            .parse::<RuleMap>()
+            .unwrap()
+            .compile()
+            .unwrap();
+        assert!(parser.matches("a"));
+        let tree = parser.parse("a").unwrap();
+        assert_eq!("0(1(2:a))", tree.to_string());
+        assert!(matches!(
+            tree.children()[0],
+            Tree::Node { alternative: 1, .. }
+        ));
+        assert_eq!(None, parser.parse("aa"));
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let rule_map = "0: 1 2\n1: \"a\"".parse::<RuleMap>().unwrap();
+        assert!(matches!(rule_map.compile(), Err(Error::UndefinedRule(2))));
+        let rule_map = "1: \"a\"".parse::<RuleMap>().unwrap();
+        assert!(matches!(rule_map.compile(), Err(Error::UndefinedRule(0))));
+        let rule_map = "0: \"a\"".parse::<RuleMap>().unwrap();
+        assert!(rule_map.compile().unwrap().matches("a"));
+    }
+
+    fn bench(name: &str, rule_map: &RuleMap, messages: &[String]) {
+        let start = std::time::Instant::now();
+        let interpreted = messages
+            .iter()
+            .map(|m| rule_map.matches(m))
+            .collect::<Vec<_>>();
+        println!("{}, interpreted: {:?}", name, start.elapsed());
+        let start = std::time::Instant::now();
+        let parser = rule_map.compile().unwrap();
+        let compiled = messages
+            .iter()
+            .map(|m| parser.matches(m))
+            .collect::<Vec<_>>();
+        println!("{}, compiled: {:?}", name, start.elapsed());
+        assert_eq!(interpreted, compiled);
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "expensive_tests"), ignore)]
+    fn bench_parser() {
+        let mut rule_map = TEST_RULES_2.parse::<RuleMap>().unwrap();
+        rule_map.rewrite(8, "42 | 42 8").unwrap();
+        rule_map.rewrite(11, "42 31 | 42 11 31").unwrap();
+        let mut state = 2020u64;
+        let messages = (0..5000)
+            .map(|i| {
+                (0..15 + i % 5 * 5)
+                    .map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        if state & 1 == 0 {
+                            'a'
+                        } else {
+                            'b'
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+        bench("5000 random messages", &rule_map, &messages);
+
+        // Every split of the a's into runs of one or two is a separate path
+        // for the interpreter when the final b is missing.
+        let rule_map = "0: 1 2\n1: 3 | 3 1 | 3 3 1\n2: \"b\"\n3: \"a\""
+            .parse::<RuleMap>()
+            .unwrap();
+        let messages = (1..=24)
+            .map(|n| "a".repeat(n) + if n % 2 == 0 { "b" } else { "a" })
+            .collect::<Vec<_>>();
+        bench("ambiguous grammar", &rule_map, &messages);
+    }
+}
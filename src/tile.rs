@@ -1,3 +1,7 @@
+use crate::animation::Frame;
+use crate::animation::Render;
+use crate::automaton::Automaton;
+use crate::automaton::Driver;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use thiserror::Error as ThisError;
@@ -11,7 +15,7 @@ pub enum Error {
 #[cfg(test)]
 type Result<T> = std::result::Result<T, Error>;
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Color {
     Black,
     White,
@@ -44,7 +48,7 @@ impl Color {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Map {
     tiles: HashMap<Coord, Color>,
 }
@@ -201,16 +205,83 @@ impl Map {
         self.tiles = new_tiles;
     }
 
+    // Skips ahead once the pattern starts repeating.
     pub fn flip_days(&mut self, num_days: usize) {
-        for _ in 0..num_days {
-            self.flip_day();
+        *self = Driver::new(self.clone()).nth(num_days);
+    }
+}
+
+impl Automaton for Map {
+    type Key = Vec<(i32, i32, i32)>;
+
+    fn key(&self) -> Self::Key {
+        let mut black = self
+            .tiles
+            .iter()
+            .filter(|(_, color)| **color == Color::Black)
+            .map(|(c, _)| (c.x, c.y, c.z))
+            .collect::<Vec<_>>();
+        black.sort_unstable();
+        black
+    }
+
+    fn step(&self) -> Self {
+        let mut next = self.clone();
+        next.flip_day();
+        next
+    }
+}
+
+// Rows of tiles going south, each offset half a tile from the ones above and
+// below it, so a tile's neighbors to the east and west are two characters
+// away and the rest are diagonally next to it.
+impl Render for Map {
+    fn render(&self) -> Frame {
+        let black = self
+            .tiles
+            .iter()
+            .filter(|(_, color)| **color == Color::Black)
+            .map(|(c, _)| c)
+            .collect::<HashSet<_>>();
+        if black.is_empty() {
+            return Frame::new(Vec::new());
         }
+        // Going east moves two columns along.
+        let col = |c: &Coord| c.y - c.x;
+        let (top, bottom) = (
+            black.iter().map(|c| c.z).min(),
+            black.iter().map(|c| c.z).max(),
+        );
+        let (left, right) = (
+            black.iter().map(|c| col(c)).min(),
+            black.iter().map(|c| col(c)).max(),
+        );
+        let rows = (top.unwrap()..=bottom.unwrap())
+            .map(|z| {
+                (left.unwrap()..=right.unwrap())
+                    .map(|col| match (col + z) % 2 {
+                        0 => {
+                            let x = -(col + z) / 2;
+                            match black.contains(&Coord { x, y: col + x, z }) {
+                                true => '#',
+                                false => '.',
+                            }
+                        }
+                        _ => ' ',
+                    })
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect();
+        Frame::new(rows)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::animation::Recorder;
 
     static TEST_INPUT: &str = &r"sesenwnenenewseeswwswswwnenewsewsw
 neeenesenwnwwswnenewnwwsewnenwseswesw
@@ -288,4 +359,38 @@ wseweeenwnesenwwwswnew";
         map.flip_days(90);
         assert_eq!(2208, map.color_count(&Color::Black));
     }
+
+    #[test]
+    fn test_render() {
+        let mut map = Map::new();
+        let coords = ["", "e", "ne", "sw", "sesw"]
+            .iter()
+            .map(|s| s.parse::<Coord>().unwrap())
+            .collect::<Vec<_>>();
+        map.flip_all(&coords);
+        assert_eq!(". #\n # #\n# .\n # .\n", map.render().to_ascii());
+        assert_eq!("", Map::new().render().to_ascii());
+
+        // Two black tiles with one white tile between them turn it black,
+        // but are too far apart to survive, and so is the new tile.
+        let mut map = Map::new();
+        map.flip_all(&["w".parse().unwrap(), "e".parse().unwrap()]);
+        let mut recorder = Recorder::new();
+        let map = recorder.run(&map, 2);
+        assert_eq!(0, map.color_count(&Color::Black));
+        assert_eq!("# . #\n", recorder.frames()[0].to_ascii());
+        assert_eq!("#\n", recorder.frames()[1].to_ascii());
+    }
+
+    #[test]
+    fn test_flip_cycle() {
+        // A lone black tile turns white and stays that way.
+        let mut map = Map::new();
+        map.flip_all(&["nwwswee".parse::<Coord>().unwrap()]);
+        let cycle = Driver::new(map.clone()).run_until_cycle();
+        assert!(cycle.is_fixed_point());
+        assert_eq!(1, cycle.pre_period);
+        map.flip_days(1_000_000_000);
+        assert_eq!(0, map.color_count(&Color::Black));
+    }
 }